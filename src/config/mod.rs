@@ -0,0 +1,339 @@
+use super::{
+    cropper::Style,
+    daemon::Action,
+    hotkey::{Binding, Chord},
+    image::Format,
+};
+
+use clap::ValueEnum;
+use custom_error::custom_error;
+use serde::{de::Error as _, Deserialize, Deserializer};
+use std::{collections::BTreeMap, env, fs, io, path::PathBuf};
+
+#[cfg_attr(windows, path = "windows.rs")]
+#[cfg_attr(unix, path = "unix.rs")]
+mod os;
+
+pub use os::watch;
+
+custom_error! { pub ConfigError
+    Read{path: String, source: io::Error} = "cannot read {path}: {source}",
+    Invalid{path: String, source: toml::de::Error} = "invalid config {path}: {source}",
+}
+
+// everything in config.toml, what's left out keeps its default
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub style: Style,
+    // None for the daemon's defaults
+    pub bindings: Option<Vec<Binding<Action>>>,
+    pub output: Option<String>,
+    pub format: Option<Format>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct File {
+    cropper: CropperSection,
+    #[serde(deserialize_with = "bindings")]
+    hotkeys: Option<Vec<Binding<Action>>>,
+    output: OutputSection,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct CropperSection {
+    #[serde(deserialize_with = "color")]
+    dim_color: [f32; 3],
+    #[serde(deserialize_with = "opacity")]
+    dim_opacity: f32,
+    #[serde(deserialize_with = "speed")]
+    animation_speed: f64,
+    adjust: bool,
+    magnifier: bool,
+}
+
+impl Default for CropperSection {
+    fn default() -> Self {
+        let style = Style::default();
+
+        CropperSection {
+            dim_color: style.dim_color,
+            dim_opacity: style.dim_opacity,
+            animation_speed: style.animation_speed,
+            adjust: style.adjust,
+            magnifier: style.magnifier,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct OutputSection {
+    path: Option<String>,
+    #[serde(deserialize_with = "format")]
+    format: Option<Format>,
+}
+
+// $XDG_CONFIG_HOME/screensnap/config.toml, or %APPDATA% on windows
+pub fn path() -> Option<PathBuf> {
+    let dir = match cfg!(windows) {
+        true => env::var_os("APPDATA").map(PathBuf::from),
+        false => env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))),
+    };
+
+    dir.map(|dir| dir.join("screensnap").join("config.toml"))
+}
+
+// the defaults when there's no config file
+pub fn load() -> Result<Config, ConfigError> {
+    let path = match path() {
+        Some(path) => path,
+        None => return Ok(Config::default()),
+    };
+
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(source) => {
+            return Err(ConfigError::Read {
+                path: path.display().to_string(),
+                source,
+            })
+        }
+    };
+
+    // toml's errors show the line, and what's wrong with it
+    let file: File = toml::from_str(&text).map_err(|source| ConfigError::Invalid {
+        path: path.display().to_string(),
+        source,
+    })?;
+
+    Ok(Config {
+        style: Style {
+            dim_color: file.cropper.dim_color,
+            dim_opacity: file.cropper.dim_opacity,
+            animation_speed: file.cropper.animation_speed,
+            adjust: file.cropper.adjust,
+            magnifier: file.cropper.magnifier,
+        },
+        bindings: file.hotkeys,
+        output: file.output.path,
+        format: file.output.format,
+    })
+}
+
+// "#rrggbb" or "#rgb"
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[f32; 3], D::Error> {
+    let text = String::deserialize(deserializer)?;
+    let invalid = || {
+        D::Error::custom(format!(
+            "expected a color like \"#1e1e2e\", not \"{}\"",
+            text
+        ))
+    };
+
+    let hex = text.strip_prefix('#').ok_or_else(invalid)?;
+    let digits: Vec<u32> = hex
+        .chars()
+        .map(|c| c.to_digit(16))
+        .collect::<Option<_>>()
+        .ok_or_else(invalid)?;
+
+    let channels = match digits[..] {
+        [r, g, b] => [r * 17, g * 17, b * 17],
+        [r1, r2, g1, g2, b1, b2] => [r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2],
+        _ => return Err(invalid()),
+    };
+
+    Ok(channels.map(|c| c as f32 / 255.0))
+}
+
+fn opacity<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    match f32::deserialize(deserializer)? {
+        opacity if (0.0..=1.0).contains(&opacity) => Ok(opacity),
+        opacity => Err(D::Error::custom(format!(
+            "must be between 0 and 1, not {}",
+            opacity
+        ))),
+    }
+}
+
+fn speed<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    match f64::deserialize(deserializer)? {
+        speed if speed > 0.0 && speed.is_finite() => Ok(speed),
+        speed => Err(D::Error::custom(format!(
+            "must be a positive number, not {}",
+            speed
+        ))),
+    }
+}
+
+fn format<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Format>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+
+    text.parse()
+        .map(Some)
+        .map_err(|e| D::Error::custom(format!("unknown format \"{}\", {}", text, e)))
+}
+
+// "Super+Shift+S" = "crop", replacing the default hotkeys
+fn bindings<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<Binding<Action>>>, D::Error> {
+    let table = BTreeMap::<String, String>::deserialize(deserializer)?;
+    let mut bindings: Vec<Binding<Action>> = Vec::with_capacity(table.len());
+
+    for (chord, action) in table {
+        let chord = chord
+            .parse::<Chord>()
+            .map_err(|e| D::Error::custom(format!("\"{}\": {}", chord, e)))?;
+
+        let action = Action::from_str(&action, true).map_err(|_| {
+            D::Error::custom(format!(
+                "\"{}\" is not an action, expected crop, crop-to-file, active-window, \
+                 screen or current-monitor",
+                action
+            ))
+        })?;
+
+        // "Ctrl+Print" and "Control+Print" are the same keys
+        if bindings.iter().any(|binding| binding.chord == chord) {
+            return Err(D::Error::custom(format!("{} is bound twice", chord)));
+        }
+
+        bindings.push(Binding { chord, action });
+    }
+
+    Ok(Some(bindings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<File, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+
+    fn assert_invalid(text: &str, message: &str) {
+        match parse(text) {
+            Ok(_) => panic!("{:?} was accepted", text),
+            Err(e) => assert!(e.contains(message), "{:?} doesn't say {:?}", e, message),
+        }
+    }
+
+    #[test]
+    fn parses_colors() {
+        let color = |text: &str| parse(&format!("[cropper]\ndim_color = \"{}\"", text));
+
+        assert_eq!(
+            color("#ff8000").unwrap().cropper.dim_color,
+            [1.0, 128.0 / 255.0, 0.0]
+        );
+        assert_eq!(
+            color("#F80").unwrap().cropper.dim_color,
+            [1.0, 136.0 / 255.0, 0.0]
+        );
+
+        for bad in ["ff8000", "#ff80", "#ff800g", "#", "#ff8000ff"] {
+            assert_invalid(
+                &format!("[cropper]\ndim_color = \"{}\"", bad),
+                &format!("expected a color like \"#1e1e2e\", not \"{}\"", bad),
+            );
+        }
+    }
+
+    #[test]
+    fn bounds_opacity_and_speed() {
+        assert_eq!(
+            parse("[cropper]\ndim_opacity = 0.25")
+                .unwrap()
+                .cropper
+                .dim_opacity,
+            0.25
+        );
+        assert_eq!(
+            parse("[cropper]\ndim_opacity = 1.0")
+                .unwrap()
+                .cropper
+                .dim_opacity,
+            1.0
+        );
+        assert_invalid(
+            "[cropper]\ndim_opacity = 1.5",
+            "must be between 0 and 1, not 1.5",
+        );
+        assert_invalid(
+            "[cropper]\ndim_opacity = -0.1",
+            "must be between 0 and 1, not -0.1",
+        );
+
+        assert_eq!(
+            parse("[cropper]\nanimation_speed = 2.5")
+                .unwrap()
+                .cropper
+                .animation_speed,
+            2.5
+        );
+        assert_invalid(
+            "[cropper]\nanimation_speed = 0.0",
+            "must be a positive number, not 0",
+        );
+        assert_invalid(
+            "[cropper]\nanimation_speed = -1.0",
+            "must be a positive number, not -1",
+        );
+        assert_invalid(
+            "[cropper]\nanimation_speed = inf",
+            "must be a positive number, not inf",
+        );
+    }
+
+    #[test]
+    fn parses_bindings() {
+        let file =
+            parse("[hotkeys]\n\"Super+Shift+S\" = \"crop\"\n\"Print\" = \"screen\"").unwrap();
+        let mut bindings = file.hotkeys.unwrap();
+        bindings.sort_by_key(|binding| binding.chord.modifiers.shift);
+
+        assert_eq!(bindings.len(), 2);
+        assert_eq!(bindings[0].chord, "Print".parse().unwrap());
+        assert_eq!(bindings[0].action, Action::Screen);
+        assert_eq!(bindings[1].chord, "Super+Shift+S".parse().unwrap());
+        assert_eq!(bindings[1].action, Action::Crop);
+
+        // no [hotkeys] keeps the defaults, an empty one binds nothing
+        assert!(parse("").unwrap().hotkeys.is_none());
+        assert_eq!(parse("[hotkeys]").unwrap().hotkeys, Some(Vec::new()));
+    }
+
+    #[test]
+    fn explains_bad_bindings() {
+        assert_invalid(
+            "[hotkeys]\n\"Hyper+S\" = \"crop\"",
+            "\"Hyper+S\": unknown modifier \"Hyper\"",
+        );
+        assert_invalid("[hotkeys]\n\"Ctrl+F25\" = \"crop\"", "unknown key \"F25\"");
+        assert_invalid(
+            "[hotkeys]\n\"Print\" = \"paint\"",
+            "\"paint\" is not an action, expected crop, crop-to-file",
+        );
+        assert_invalid(
+            "[hotkeys]\n\"Ctrl+Print\" = \"crop\"\n\"Control+Print\" = \"screen\"",
+            "is bound twice",
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert_invalid(
+            "[cropper]\ndim_colour = \"#000\"",
+            "unknown field `dim_colour`",
+        );
+        assert_invalid("[outputs]\npath = \"a.png\"", "unknown field `outputs`");
+        assert_invalid("[output]\nformat = \"gif\"", "unknown format \"gif\"");
+    }
+}
@@ -0,0 +1,71 @@
+use inotify::{Inotify, WatchMask};
+use std::{ffi::OsString, fs, io, path::Path, thread};
+
+// calls back whenever the file is written, replaced or removed, until the
+// callback returns true
+pub fn watch<F>(path: &Path, mut changed: F) -> io::Result<()>
+where
+    F: FnMut() -> bool + Send + 'static,
+{
+    let (dir, name) = match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => (dir, OsString::from(name)),
+        _ => return Err(io::ErrorKind::InvalidInput.into()),
+    };
+
+    // there's usually no directory before the first config, and a watch
+    // can't be added on what doesn't exist
+    fs::create_dir_all(dir)?;
+
+    // editors often save to another file and rename it over the config, so
+    // the directory is watched rather than the file
+    let inotify = Inotify::init()?;
+    inotify.watches().add(
+        dir,
+        WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::DELETE,
+    )?;
+
+    thread::spawn(move || {
+        let mut inotify = inotify;
+        let mut buffer = [0; 4096];
+
+        while let Ok(events) = inotify.read_events_blocking(&mut buffer) {
+            // a save can be a few events, one reload is enough
+            let touched = events
+                .into_iter()
+                .any(|event| event.name == Some(name.as_os_str()));
+
+            if touched && changed() {
+                break;
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{sync::mpsc, time::Duration};
+
+    #[test]
+    fn sees_a_config_created_in_a_new_directory() {
+        let dir = std::env::temp_dir().join(format!("screensnap-watch-{}", std::process::id()));
+        let path = dir.join("screensnap").join("config.toml");
+        let _ = fs::remove_dir_all(&dir);
+
+        let (sender, changed) = mpsc::channel();
+        watch(&path, move || sender.send(()).is_err()).unwrap();
+
+        // written elsewhere and renamed over, like editors do
+        fs::write(dir.join("screensnap").join(".config.toml.swp"), "").unwrap();
+        fs::write(dir.join("screensnap").join("config.toml.new"), "").unwrap();
+        fs::rename(dir.join("screensnap").join("config.toml.new"), &path).unwrap();
+
+        let seen = changed.recv_timeout(Duration::from_secs(5));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(seen.is_ok());
+    }
+}
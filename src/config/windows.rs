@@ -0,0 +1,54 @@
+use std::{ffi::OsStr, fs, io, iter::once, os::windows::ffi::OsStrExt, path::Path, thread};
+
+use winapi::um::{
+    fileapi::{
+        FindCloseChangeNotification, FindFirstChangeNotificationW, FindNextChangeNotification,
+    },
+    handleapi::INVALID_HANDLE_VALUE,
+    synchapi::WaitForSingleObject,
+    winbase::{INFINITE, WAIT_OBJECT_0},
+    winnt::{FILE_NOTIFY_CHANGE_FILE_NAME, FILE_NOTIFY_CHANGE_LAST_WRITE, HANDLE},
+};
+
+// calls back whenever a file in the config's directory changes, until the
+// callback returns true
+pub fn watch<F>(path: &Path, mut changed: F) -> io::Result<()>
+where
+    F: FnMut() -> bool + Send + 'static,
+{
+    let dir = path.parent().ok_or(io::ErrorKind::InvalidInput)?;
+
+    // there's usually no directory before the first config
+    fs::create_dir_all(dir)?;
+
+    let dir: Vec<u16> = OsStr::new(dir).encode_wide().chain(once(0)).collect();
+
+    let handle = unsafe {
+        FindFirstChangeNotificationW(
+            dir.as_ptr(),
+            0,
+            FILE_NOTIFY_CHANGE_FILE_NAME | FILE_NOTIFY_CHANGE_LAST_WRITE,
+        )
+    };
+
+    if handle == INVALID_HANDLE_VALUE {
+        return Err(io::Error::last_os_error());
+    }
+
+    // handles can't be sent as they are
+    let handle = handle as usize;
+
+    thread::spawn(move || {
+        let handle = handle as HANDLE;
+
+        while unsafe { WaitForSingleObject(handle, INFINITE) } == WAIT_OBJECT_0 {
+            if changed() || unsafe { FindNextChangeNotification(handle) } == 0 {
+                break;
+            }
+        }
+
+        unsafe { FindCloseChangeNotification(handle) };
+    });
+
+    Ok(())
+}
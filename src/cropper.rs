@@ -1,391 +1,1059 @@
-use super::{
-    focuser,
-    screengrab::{Rectangle, Screenshot},
-};
-use custom_error::custom_error;
-use easer::functions::Easing;
-use glium::{
-    self,
-    backend::glutin::DisplayCreationError,
-    glutin::{
-        dpi::LogicalPosition, ContextBuilder, ElementState, Event, EventsLoop, KeyboardInput,
-        ModifiersState, MouseButton, VirtualKeyCode, WindowBuilder, WindowEvent,
-    },
-    implement_vertex,
-    index::{BufferCreationError as IboCreationError, IndexBuffer, PrimitiveType},
-    program,
-    program::{Program, ProgramChooserCreationError},
-    texture::{MipmapsOption, RawImage2d, SrgbTexture2d, TextureCreationError},
-    uniform,
-    vertex::{BufferCreationError as VboCreationError, VertexBuffer},
-    Blend, Display, DrawError, DrawParameters, Surface, SwapBuffersError,
-};
-use std::time::{Duration, Instant};
-
-// custom error type
-custom_error! { pub CropperError
-    DisplayCreation{source: DisplayCreationError} = "cannot create display: {source:?}",
-    Swap{source: SwapBuffersError} = "cannot swap buffers: {source:?}",
-    TextureCreation{source: TextureCreationError} = "cannot create texture: {source:?}",
-    VboCreation{source: VboCreationError} = "cannot create vbo: {source:?}",
-    IndexBufferCreation{source: IboCreationError} = "cannot create index buffer: {source:?}",
-    ProgramCreation{source: ProgramChooserCreationError} = "cannot create program: {source:?}",
-    Draw{source: DrawError} = "error when drawing: {source:?}",
-}
-
-// vertex buffer type
-#[derive(Debug, Copy, Clone)]
-struct Vertex {
-    pos: [f32; 2],
-}
-
-implement_vertex!(Vertex, pos);
-
-// structure holding the programs we use
-struct CropperPrograms {
-    full_quad_tex: Program,
-    sub_quad_tex: Program,
-}
-
-struct CroppingContext {
-    started: Instant,
-    delta: Duration,
-
-    snap: Screenshot,
-    snap_tex: SrgbTexture2d,
-
-    region: Option<Rectangle<f64>>,
-    animated_region: Option<Rectangle<f64>>,
-    region_appear_time: Option<Instant>,
-}
-
-// structure holding everything else we'll need
-pub struct Cropper {
-    events_loop: EventsLoop,
-    display: Display,
-
-    vbo: VertexBuffer<Vertex>,
-    index_buffer: IndexBuffer<u16>,
-    programs: CropperPrograms,
-}
-
-// where we do the cool stuff
-impl Cropper {
-    pub fn new() -> Result<Cropper, CropperError> {
-        let events_loop = EventsLoop::new();
-
-        let display = Display::new(
-            WindowBuilder::new()
-                .with_title("Screenshot")
-                .with_visibility(false)
-                .with_always_on_top(true)
-                .with_decorations(false)
-                .with_resizable(false),
-            ContextBuilder::new().with_vsync(true),
-            &events_loop,
-        )?;
-
-        Ok(Cropper {
-            // create a fullscreen quad VBO
-            vbo: VertexBuffer::new(
-                &display,
-                &[
-                    Vertex { pos: [0.0, 0.0] },
-                    Vertex { pos: [1.0, 0.0] },
-                    Vertex { pos: [0.0, 1.0] },
-                    Vertex { pos: [1.0, 1.0] },
-                ],
-            )?,
-
-            // indices for the VBO
-            index_buffer: IndexBuffer::new(
-                &display,
-                PrimitiveType::TriangleStrip,
-                &[0 as u16, 1, 2, 3],
-            )?,
-
-            // all the programs we need
-            programs: CropperPrograms {
-                full_quad_tex: program!(&display,
-                    140 => {
-                        vertex: include_str!("shaders/full_quad_tex/140.vs"),
-                        fragment: include_str!("shaders/full_quad_tex/140.fs"),
-                    }
-                )?,
-
-                sub_quad_tex: program!(&display,
-                    140 => {
-                        vertex: include_str!("shaders/sub_quad_tex/140.vs"),
-                        fragment: include_str!("shaders/sub_quad_tex/140.fs"),
-                    }
-                )?,
-            },
-
-            events_loop,
-            display,
-        })
-    }
-
-    pub fn apply(&mut self, snap: Screenshot) -> Result<bool, CropperError> {
-        self.display
-            .gl_window()
-            .window()
-            .set_max_dimensions(Some((snap.bounds.w as u32, snap.bounds.h as u32).into()));
-        self.display
-            .gl_window()
-            .window()
-            .set_min_dimensions(Some((snap.bounds.w as u32, snap.bounds.h as u32).into()));
-        self.display
-            .gl_window()
-            .window()
-            .set_position((snap.bounds.x, snap.bounds.y).into());
-        self.display.gl_window().window().show();
-
-        // glutin doesn't let us bring the window to the foreground.
-        // well, glutin, im doing it anyway.
-        focuser::focus_current_window();
-
-        let mut context = CroppingContext {
-            started: Instant::now(),
-            delta: Default::default(),
-
-            region: None,
-            animated_region: None,
-            region_appear_time: None,
-
-            snap_tex: SrgbTexture2d::with_mipmaps(
-                &self.display,
-                RawImage2d::from_raw_rgb(
-                    snap.data.clone(),
-                    (snap.bounds.w as u32, snap.bounds.h as u32),
-                ),
-                MipmapsOption::NoMipmap,
-            )?,
-            snap: snap,
-        };
-
-        // becomes true whenever the window should close
-        let mut closed = false;
-
-        // the value this function returns
-        let mut should_quit = false;
-
-        // where the left mouse button was pressed
-        let mut left_press: Option<(f64, f64)> = None;
-
-        // tracks the position of the cursor
-        let mut cursor_pos = (0.0, 0.0);
-
-        // right now
-        let mut now = Instant::now();
-
-        // empty the event queue
-        self.events_loop.poll_events(|_| ());
-
-        // main loop
-        while !closed {
-            context.delta = now.elapsed();
-            now = Instant::now();
-
-            // create a frame
-            let mut frame = self.display.draw();
-
-            // store the result of the render
-            let render_result = self.render(&mut frame, &mut context);
-
-            // finish the frame first
-            frame.finish()?;
-
-            // then we can check the result
-            render_result?;
-
-            // handle events
-            self.events_loop.poll_events(|e| match e {
-                // window events
-                Event::WindowEvent { event, .. } => match event {
-                    // kill process
-                    WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                virtual_keycode: Some(VirtualKeyCode::Q),
-                                state: ElementState::Pressed,
-                                modifiers:
-                                    ModifiersState {
-                                        ctrl: true,
-                                        shift: true,
-                                        ..
-                                    },
-                                ..
-                            },
-                        ..
-                    } => {
-                        should_quit = true;
-                        closed = true
-                    }
-
-                    // cancel screenshot
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                state: ElementState::Pressed,
-                                ..
-                            },
-                        ..
-                    } => {
-                        // set region to None do cancel
-                        context.region = None;
-                        closed = true
-                    }
-
-                    // cursor moved
-                    WindowEvent::CursorMoved {
-                        position: LogicalPosition { x, y },
-                        modifiers,
-                        ..
-                    } => {
-                        cursor_pos = (x, y);
-
-                        if let Some((px, py)) = left_press {
-                            context.region = Some(Rectangle {
-                                x: px.min(x),
-                                y: py.min(y),
-                                w: (px - x).abs(),
-                                h: (py - y).abs(),
-                            });
-
-                            // disable animation
-                            context.animated_region = context.region;
-                        } else {
-                            match modifiers {
-                                ModifiersState { shift: true, .. } => {
-                                    context.region = context
-                                        .snap
-                                        .windows
-                                        .iter()
-                                        .find(|w| w.bounds.contains(x as i32, y as i32))
-                                        .map(|w| Rectangle {
-                                            x: w.bounds.x as f64,
-                                            y: w.bounds.y as f64,
-                                            w: w.bounds.w as f64,
-                                            h: w.bounds.h as f64,
-                                        })
-                                }
-                                _ => context.region = None,
-                            }
-                        }
-                    }
-
-                    // mouse input
-                    WindowEvent::MouseInput { button, state, .. } => match (button, state) {
-                        (MouseButton::Left, ElementState::Released) => closed = true,
-                        (MouseButton::Left, ElementState::Pressed) => left_press = Some(cursor_pos),
-                        _ => (),
-                    },
-
-                    // other window events
-                    _ => (),
-                },
-
-                // other events
-                _ => (),
-            });
-        }
-
-        self.display.gl_window().window().hide();
-
-        // copy to clipboard!
-        if let Some(region) = context.region {
-            context.snap.copy_to_clipboard(Rectangle {
-                x: region.x as u32,
-                y: region.y as u32,
-                w: region.w as u32,
-                h: region.h as u32,
-            });
-        }
-
-        Ok(should_quit)
-    }
-
-    fn render(
-        &mut self,
-        frame: &mut glium::Frame,
-        ctx: &mut CroppingContext,
-    ) -> Result<(), CropperError> {
-        if let (Some(areg), Some(reg)) = (ctx.animated_region, ctx.region) {
-            let delta_s = ctx.delta.as_millis() as f64 / 1000.0;
-
-            ctx.animated_region = Some(Rectangle {
-                x: areg.x + (reg.x - areg.x) * delta_s * 20.0,
-                y: areg.y + (reg.y - areg.y) * delta_s * 20.0,
-                w: areg.w + (reg.w - areg.w) * delta_s * 20.0,
-                h: areg.h + (reg.h - areg.h) * delta_s * 20.0,
-            });
-        } else {
-            ctx.animated_region = ctx.region;
-        }
-
-        let draw_params = DrawParameters {
-            blend: Blend::alpha_blending(),
-            ..Default::default()
-        };
-
-        // clear to black
-        frame.clear_color(0.0, 0.0, 0.0, 1.0);
-
-        // base pass
-        let uniforms = uniform! {
-            tex: &ctx.snap_tex,
-            opacity: easer::functions::Cubic::ease_out(
-                ctx.started.elapsed().as_millis().min(200) as f32,
-                1.0f32,
-                -0.5f32,
-                200.0f32
-            ),
-        };
-
-        frame.draw(
-            &self.vbo,
-            &self.index_buffer,
-            &self.programs.full_quad_tex,
-            &uniforms,
-            &draw_params,
-        )?;
-
-        // active region pass
-        if let Some(areg) = ctx.animated_region {
-            if let None = ctx.region_appear_time {
-                ctx.region_appear_time = Some(Instant::now());
-            }
-
-            let uniforms = uniform! {
-                tex: &ctx.snap_tex,
-                opacity: easer::functions::Cubic::ease_out(
-                    ctx.region_appear_time.unwrap().elapsed().as_millis().min(200) as f32,
-                    0.5f32,
-                    0.5f32,
-                    200.0f32
-                ),
-                bounds: [
-                    (areg.x as f32) / (ctx.snap.bounds.w as f32),
-                    1.0 - (areg.y as f32) / (ctx.snap.bounds.h as f32),
-                    (areg.w as f32) / (ctx.snap.bounds.w as f32),
-                    -(areg.h as f32) / (ctx.snap.bounds.h as f32)
-                ],
-            };
-
-            frame.draw(
-                &self.vbo,
-                &self.index_buffer,
-                &self.programs.sub_quad_tex,
-                &uniforms,
-                &draw_params,
-            )?;
-        } else {
-            ctx.region_appear_time = None;
-        }
-
-        Ok(())
-    }
-}
+use super::{
+    focuser,
+    screengrab::{CaptureMode, Rectangle, Screenshot},
+};
+use custom_error::custom_error;
+use easer::functions::Easing;
+use glium::{
+    self,
+    backend::glutin::DisplayCreationError,
+    glutin::{
+        dpi::LogicalPosition, ContextBuilder, ElementState, Event, EventsLoop, KeyboardInput,
+        ModifiersState, MouseButton, MouseCursor, VirtualKeyCode, WindowBuilder, WindowEvent,
+    },
+    implement_vertex,
+    index::{BufferCreationError as IboCreationError, IndexBuffer, PrimitiveType},
+    program,
+    program::{Program, ProgramChooserCreationError},
+    texture::{MipmapsOption, RawImage2d, SrgbTexture2d, TextureCreationError},
+    uniform,
+    vertex::{BufferCreationError as VboCreationError, VertexBuffer},
+    Blend, Display, DrawError, DrawParameters, Surface, SwapBuffersError,
+};
+use std::time::{Duration, Instant};
+
+// custom error type
+custom_error! { pub CropperError
+    DisplayCreation{source: DisplayCreationError} = "cannot create display: {source:?}",
+    Swap{source: SwapBuffersError} = "cannot swap buffers: {source:?}",
+    TextureCreation{source: TextureCreationError} = "cannot create texture: {source:?}",
+    VboCreation{source: VboCreationError} = "cannot create vbo: {source:?}",
+    IndexBufferCreation{source: IboCreationError} = "cannot create index buffer: {source:?}",
+    ProgramCreation{source: ProgramChooserCreationError} = "cannot create program: {source:?}",
+    Draw{source: DrawError} = "error when drawing: {source:?}",
+}
+
+// vertex buffer type
+#[derive(Debug, Copy, Clone)]
+struct Vertex {
+    pos: [f32; 2],
+}
+
+implement_vertex!(Vertex, pos);
+
+// structure holding the programs we use
+struct CropperPrograms {
+    full_quad_tex: Program,
+    sub_quad_tex: Program,
+    handle: Program,
+    loupe: Program,
+    label: Program,
+}
+
+// size of the adjust mode's handles, and of the area that grabs them, in pixels
+const HANDLE_SIZE: f64 = 8.0;
+const HANDLE_GRAB_SIZE: f64 = 14.0;
+
+// the magnifier shows that many pixels across, that much bigger, that far
+// from the cursor
+const LOUPE_PIXELS: f64 = 15.0;
+const LOUPE_ZOOM: f64 = 8.0;
+const LOUPE_OFFSET: f64 = 24.0;
+
+// size of a font pixel of the magnifier's coordinates, in screen pixels
+const LABEL_SCALE: f64 = 2.0;
+
+// two clicks closer than this are a double-click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+// the edges a handle drags: -1 for the left (top) one, 1 for the right
+// (bottom) one. (0, 0) is the move grip, in the middle
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Handle {
+    x: i8,
+    y: i8,
+}
+
+impl Handle {
+    // the corners and edges, then the grip
+    const ALL: [Handle; 9] = [
+        Handle { x: -1, y: -1 },
+        Handle { x: 0, y: -1 },
+        Handle { x: 1, y: -1 },
+        Handle { x: -1, y: 0 },
+        Handle { x: 1, y: 0 },
+        Handle { x: -1, y: 1 },
+        Handle { x: 0, y: 1 },
+        Handle { x: 1, y: 1 },
+        Handle { x: 0, y: 0 },
+    ];
+
+    fn is_grip(self) -> bool {
+        self.x == 0 && self.y == 0
+    }
+
+    // where it's drawn on a region
+    fn center(self, region: Rectangle<f64>) -> (f64, f64) {
+        (
+            region.x + region.w * (self.x + 1) as f64 / 2.0,
+            region.y + region.h * (self.y + 1) as f64 / 2.0,
+        )
+    }
+
+    // the handle under the cursor. anywhere else in the region moves it too
+    fn under(region: Rectangle<f64>, (x, y): (f64, f64)) -> Option<Handle> {
+        let grabbed = Handle::ALL.iter().copied().find(|handle| {
+            let (cx, cy) = handle.center(region);
+            (x - cx).abs() <= HANDLE_GRAB_SIZE / 2.0 && (y - cy).abs() <= HANDLE_GRAB_SIZE / 2.0
+        });
+
+        let inside =
+            x >= region.x && x < region.x + region.w && y >= region.y && y < region.y + region.h;
+
+        grabbed.or(match inside {
+            true => Some(Handle { x: 0, y: 0 }),
+            false => None,
+        })
+    }
+
+    fn cursor(self) -> MouseCursor {
+        match (self.x, self.y) {
+            (0, 0) => MouseCursor::Move,
+            (-1, -1) | (1, 1) => MouseCursor::NwseResize,
+            (1, -1) | (-1, 1) => MouseCursor::NeswResize,
+            (_, 0) => MouseCursor::EwResize,
+            _ => MouseCursor::NsResize,
+        }
+    }
+
+    // the region as it was when grabbed, dragged by (dx, dy), within the
+    // screenshot
+    fn drag(
+        self,
+        region: Rectangle<f64>,
+        (dx, dy): (f64, f64),
+        bounds: (f64, f64),
+    ) -> Rectangle<f64> {
+        let (w, h) = bounds;
+
+        if self.is_grip() {
+            return Rectangle {
+                x: (region.x + dx).max(0.0).min(w - region.w),
+                y: (region.y + dy).max(0.0).min(h - region.h),
+                ..region
+            };
+        }
+
+        let mut left = region.x;
+        let mut top = region.y;
+        let mut right = region.x + region.w;
+        let mut bottom = region.y + region.h;
+
+        match self.x {
+            -1 => left = (left + dx).max(0.0).min(w),
+            1 => right = (right + dx).max(0.0).min(w),
+            _ => (),
+        }
+
+        match self.y {
+            -1 => top = (top + dy).max(0.0).min(h),
+            1 => bottom = (bottom + dy).max(0.0).min(h),
+            _ => (),
+        }
+
+        // dragging an edge past the other one flips the region
+        Rectangle {
+            x: left.min(right),
+            y: top.min(bottom),
+            w: (right - left).abs(),
+            h: (bottom - top).abs(),
+        }
+    }
+}
+
+// how the cropping window looks, see the [cropper] section of the config
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Style {
+    // what the screenshot fades to
+    pub dim_color: [f32; 3],
+    // how much it fades, from 0 to 1
+    pub dim_opacity: f32,
+    // how fast the selection catches up with the mouse
+    pub animation_speed: f64,
+    // releasing the mouse leaves handles to adjust the selection, and Enter
+    // (or a double-click) confirms it
+    pub adjust: bool,
+    // whether the magnifier starts shown, Z toggles it
+    pub magnifier: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            dim_color: [0.0, 0.0, 0.0],
+            dim_opacity: 0.5,
+            animation_speed: 20.0,
+            adjust: false,
+            magnifier: false,
+        }
+    }
+}
+
+// what the user did with the cropper
+#[derive(Debug, Copy, Clone)]
+pub enum Selection {
+    // relative to the screenshot
+    Region(Rectangle<u32>),
+    // Ctrl+S, or Ctrl held when releasing the mouse
+    Save(Rectangle<u32>),
+    Cancelled,
+    // Ctrl+Shift+Q
+    Quit,
+}
+
+struct CroppingContext<'a> {
+    started: Instant,
+    delta: Duration,
+
+    snap: &'a Screenshot,
+    snap_tex: SrgbTexture2d,
+
+    region: Option<Rectangle<f64>>,
+    animated_region: Option<Rectangle<f64>>,
+    region_appear_time: Option<Instant>,
+
+    // the region has been selected, and shows its handles
+    adjusting: bool,
+
+    // where the magnifier looks, if it's shown
+    cursor: (f64, f64),
+    magnifier: bool,
+    // the coordinates it shows, and their texture
+    label: Option<(String, SrgbTexture2d)>,
+}
+
+// structure holding everything else we'll need
+pub struct Cropper {
+    events_loop: EventsLoop,
+    display: Display,
+
+    vbo: VertexBuffer<Vertex>,
+    index_buffer: IndexBuffer<u16>,
+    programs: CropperPrograms,
+
+    style: Style,
+}
+
+// where we do the cool stuff
+impl Cropper {
+    pub fn new() -> Result<Cropper, CropperError> {
+        let events_loop = EventsLoop::new();
+
+        let display = Display::new(
+            WindowBuilder::new()
+                .with_title("Screenshot")
+                .with_visibility(false)
+                .with_always_on_top(true)
+                .with_decorations(false)
+                .with_resizable(false),
+            ContextBuilder::new().with_vsync(true),
+            &events_loop,
+        )?;
+
+        Ok(Cropper {
+            // create a fullscreen quad VBO
+            vbo: VertexBuffer::new(
+                &display,
+                &[
+                    Vertex { pos: [0.0, 0.0] },
+                    Vertex { pos: [1.0, 0.0] },
+                    Vertex { pos: [0.0, 1.0] },
+                    Vertex { pos: [1.0, 1.0] },
+                ],
+            )?,
+
+            // indices for the VBO
+            index_buffer: IndexBuffer::new(
+                &display,
+                PrimitiveType::TriangleStrip,
+                &[0u16, 1, 2, 3],
+            )?,
+
+            // all the programs we need
+            programs: CropperPrograms {
+                full_quad_tex: program!(&display,
+                    140 => {
+                        vertex: include_str!("shaders/full_quad_tex/140.vs"),
+                        fragment: include_str!("shaders/full_quad_tex/140.fs"),
+                    }
+                )?,
+
+                sub_quad_tex: program!(&display,
+                    140 => {
+                        vertex: include_str!("shaders/sub_quad_tex/140.vs"),
+                        fragment: include_str!("shaders/sub_quad_tex/140.fs"),
+                    }
+                )?,
+
+                handle: program!(&display,
+                    140 => {
+                        vertex: include_str!("shaders/handle/140.vs"),
+                        fragment: include_str!("shaders/handle/140.fs"),
+                    }
+                )?,
+
+                loupe: program!(&display,
+                    140 => {
+                        vertex: include_str!("shaders/loupe/140.vs"),
+                        fragment: include_str!("shaders/loupe/140.fs"),
+                    }
+                )?,
+
+                label: program!(&display,
+                    140 => {
+                        vertex: include_str!("shaders/label/140.vs"),
+                        fragment: include_str!("shaders/label/140.fs"),
+                    }
+                )?,
+            },
+
+            events_loop,
+            display,
+
+            style: Style::default(),
+        })
+    }
+
+    // takes effect on the next screenshot, the display stays as it is
+    pub fn set_style(&mut self, style: Style) {
+        self.style = style;
+    }
+
+    pub fn apply(&mut self, snap: &Screenshot) -> Result<Selection, CropperError> {
+        self.display
+            .gl_window()
+            .window()
+            .set_max_dimensions(Some((snap.bounds.w as u32, snap.bounds.h as u32).into()));
+        self.display
+            .gl_window()
+            .window()
+            .set_min_dimensions(Some((snap.bounds.w as u32, snap.bounds.h as u32).into()));
+        self.display
+            .gl_window()
+            .window()
+            .set_position((snap.bounds.x, snap.bounds.y).into());
+        self.display.gl_window().window().show();
+
+        // glutin doesn't let us bring the window to the foreground.
+        // well, glutin, im doing it anyway.
+        focuser::focus_current_window();
+
+        let mut context = CroppingContext {
+            started: Instant::now(),
+            delta: Default::default(),
+
+            region: None,
+            animated_region: None,
+            region_appear_time: None,
+
+            adjusting: false,
+
+            cursor: (0.0, 0.0),
+            magnifier: self.style.magnifier,
+            label: None,
+
+            snap_tex: SrgbTexture2d::with_mipmaps(
+                &self.display,
+                RawImage2d::from_raw_rgb(
+                    snap.data.clone(),
+                    (snap.bounds.w as u32, snap.bounds.h as u32),
+                ),
+                MipmapsOption::NoMipmap,
+            )?,
+            snap,
+        };
+
+        // becomes true whenever the window should close
+        let mut closed = false;
+
+        // becomes true on Ctrl+Shift+Q
+        let mut should_quit = false;
+
+        // becomes true when the region should be saved to a file
+        let mut save = false;
+
+        // where the left mouse button was pressed
+        let mut left_press: Option<(f64, f64)> = None;
+
+        // the handle being dragged, where, and the region when it was grabbed
+        let mut grabbed: Option<(Handle, (f64, f64), Rectangle<f64>)> = None;
+
+        // when the left mouse button was last pressed, for double-clicks
+        let mut last_click: Option<(Instant, (f64, f64))> = None;
+
+        // what the mouse cursor should look like, and what it looks like
+        let mut cursor = MouseCursor::Default;
+        let mut shown_cursor = cursor;
+
+        // releasing the mouse doesn't close the window
+        let adjust = self.style.adjust;
+
+        // the window Tab last went to
+        let mut window_index: Option<usize> = None;
+
+        // tracks the position of the cursor, where the arrow keys start from
+        let mut cursor_pos = match snap.cursor {
+            Some((x, y)) => (x as f64, y as f64),
+            None => (snap.bounds.w as f64 / 2.0, snap.bounds.h as f64 / 2.0),
+        };
+
+        // right now
+        let mut now = Instant::now();
+
+        // empty the event queue
+        self.events_loop.poll_events(|_| ());
+
+        // main loop
+        while !closed {
+            context.delta = now.elapsed();
+            now = Instant::now();
+            context.cursor = cursor_pos;
+
+            // create a frame
+            let mut frame = self.display.draw();
+
+            // store the result of the render
+            let render_result = self.render(&mut frame, &mut context);
+
+            // finish the frame first
+            frame.finish()?;
+
+            // then we can check the result
+            render_result?;
+
+            // where the arrow keys moved the mouse to
+            let mut warp = None;
+
+            // handle events
+            self.events_loop.poll_events(|e| {
+                // where the cursor went, and whether Shift was held
+                let mut moved = None;
+
+                // window events
+                if let Event::WindowEvent { event, .. } = e {
+                    match event {
+                        // kill process
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::Q),
+                                    state: ElementState::Pressed,
+                                    modifiers:
+                                        ModifiersState {
+                                            ctrl: true,
+                                            shift: true,
+                                            ..
+                                        },
+                                    ..
+                                },
+                            ..
+                        } => {
+                            should_quit = true;
+                            closed = true
+                        }
+
+                        // save to a file
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::S),
+                                    state: ElementState::Pressed,
+                                    modifiers: ModifiersState { ctrl: true, .. },
+                                    ..
+                                },
+                            ..
+                        } if context.region.is_some() => {
+                            save = true;
+                            closed = true
+                        }
+
+                        // confirm the region
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode:
+                                        Some(VirtualKeyCode::Return)
+                                        | Some(VirtualKeyCode::NumpadEnter),
+                                    state: ElementState::Pressed,
+                                    modifiers,
+                                    ..
+                                },
+                            ..
+                        } if context.region.is_some() => {
+                            save = modifiers.ctrl;
+                            closed = true
+                        }
+
+                        // cancel screenshot
+                        WindowEvent::CloseRequested
+                        | WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::Escape),
+                                    state: ElementState::Pressed,
+                                    ..
+                                },
+                            ..
+                        } => {
+                            // set region to None do cancel
+                            context.region = None;
+                            closed = true
+                        }
+
+                        // select with the keyboard
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(key),
+                                    state: ElementState::Pressed,
+                                    modifiers,
+                                    ..
+                                },
+                            ..
+                        } => {
+                            let step = if modifiers.shift { 10.0 } else { 1.0 };
+                            let bounds =
+                                (context.snap.bounds.w as f64, context.snap.bounds.h as f64);
+
+                            let arrow = match key {
+                                VirtualKeyCode::Left => Some((-step, 0.0)),
+                                VirtualKeyCode::Right => Some((step, 0.0)),
+                                VirtualKeyCode::Up => Some((0.0, -step)),
+                                VirtualKeyCode::Down => Some((0.0, step)),
+                                _ => None,
+                            };
+
+                            match (key, arrow, context.region) {
+                                // nudge the selection, or resize it with Alt
+                                (_, Some(delta), Some(region)) if context.adjusting => {
+                                    let handle = match modifiers.alt {
+                                        true => Handle { x: 1, y: 1 },
+                                        false => Handle { x: 0, y: 0 },
+                                    };
+                                    let region = handle.drag(region, delta, bounds);
+
+                                    if region.w >= 1.0 && region.h >= 1.0 {
+                                        context.region = Some(region);
+                                        context.animated_region = context.region;
+                                    }
+                                }
+
+                                // move the mouse, which drags the selection when
+                                // there's one going
+                                (_, Some((dx, dy)), _) => {
+                                    let pos = (
+                                        (cursor_pos.0 + dx).max(0.0).min(bounds.0 - 1.0),
+                                        (cursor_pos.1 + dy).max(0.0).min(bounds.1 - 1.0),
+                                    );
+
+                                    moved = Some((pos, false));
+                                    warp = Some(pos);
+                                }
+
+                                // finish the selection
+                                (VirtualKeyCode::Space, _, region) if left_press.is_some() => {
+                                    left_press = None;
+                                    context.adjusting =
+                                        region.is_some_and(|region| region.w >= 1.0 && region.h >= 1.0);
+                                }
+
+                                // start one
+                                (VirtualKeyCode::Space, _, _) => {
+                                    context.adjusting = false;
+                                    context.region = None;
+                                    left_press = Some(cursor_pos);
+                                }
+
+                                // show or hide the magnifier
+                                (VirtualKeyCode::Z, _, _) => {
+                                    context.magnifier = !context.magnifier
+                                }
+
+                                // go through the windows, topmost first
+                                (VirtualKeyCode::Tab, _, _) if !context.snap.windows.is_empty() => {
+                                    let count = context.snap.windows.len();
+
+                                    let index = match (window_index, modifiers.shift) {
+                                        (None, false) => 0,
+                                        (None, true) => count - 1,
+                                        (Some(i), false) => (i + 1) % count,
+                                        (Some(i), true) => (i + count - 1) % count,
+                                    };
+                                    window_index = Some(index);
+
+                                    // only what's on the screen
+                                    let bounds = context.snap.windows[index].bounds;
+                                    context.region = CaptureMode::Region(bounds)
+                                        .region(context.snap)
+                                        .ok()
+                                        .map(|region| Rectangle {
+                                            x: region.x as f64,
+                                            y: region.y as f64,
+                                            w: region.w as f64,
+                                            h: region.h as f64,
+                                        });
+
+                                    left_press = None;
+                                    context.adjusting = context.region.is_some();
+                                }
+
+                                _ => (),
+                            }
+                        }
+
+                        // cursor moved
+                        WindowEvent::CursorMoved {
+                            position: LogicalPosition { x, y },
+                            modifiers,
+                            ..
+                        } if (x, y) != cursor_pos => moved = Some(((x, y), modifiers.shift)),
+
+                        // mouse input
+                        WindowEvent::MouseInput {
+                            button,
+                            state,
+                            modifiers,
+                            ..
+                        } => match (button, state) {
+                            // done moving or resizing
+                            (MouseButton::Left, ElementState::Released) if grabbed.is_some() => {
+                                grabbed = None
+                            }
+
+                            // keep the region around to adjust it
+                            (MouseButton::Left, ElementState::Released) if adjust => {
+                                left_press = None;
+                                context.adjusting = context
+                                    .region
+                                    .is_some_and(|region| region.w >= 1.0 && region.h >= 1.0);
+                            }
+
+                            (MouseButton::Left, ElementState::Released) => {
+                                save = modifiers.ctrl;
+                                closed = true
+                            }
+
+                            (MouseButton::Left, ElementState::Pressed) => {
+                                let double_click = last_click.is_some_and(|(time, (x, y))| {
+                                    time.elapsed() < DOUBLE_CLICK
+                                        && (x - cursor_pos.0).abs() < 4.0
+                                        && (y - cursor_pos.1).abs() < 4.0
+                                });
+                                last_click = Some((Instant::now(), cursor_pos));
+
+                                let handle = context
+                                    .region
+                                    .filter(|_| context.adjusting)
+                                    .and_then(|region| Handle::under(region, cursor_pos));
+
+                                match (double_click, handle) {
+                                    (true, Some(_)) => {
+                                        save = modifiers.ctrl;
+                                        closed = true
+                                    }
+                                    (false, Some(handle)) => {
+                                        grabbed =
+                                            Some((handle, cursor_pos, context.region.unwrap()))
+                                    }
+
+                                    // start over
+                                    (_, None) => {
+                                        context.adjusting = false;
+                                        cursor = MouseCursor::Default;
+                                        left_press = Some(cursor_pos)
+                                    }
+                                }
+                            }
+                            _ => (),
+                        },
+
+                        // other window events
+                        _ => (),
+                    }
+                }
+
+                if let Some(((x, y), shift)) = moved {
+                    cursor_pos = (x, y);
+
+                    if let Some((handle, (gx, gy), region)) = grabbed {
+                        let bounds =
+                            (context.snap.bounds.w as f64, context.snap.bounds.h as f64);
+                        context.region = Some(handle.drag(region, (x - gx, y - gy), bounds));
+
+                        // disable animation
+                        context.animated_region = context.region;
+                    } else if context.adjusting {
+                        // show what the handles do
+                        cursor = context
+                            .region
+                            .and_then(|region| Handle::under(region, (x, y)))
+                            .map(Handle::cursor)
+                            .unwrap_or(MouseCursor::Default);
+                    } else if let Some((px, py)) = left_press {
+                        context.region = Some(Rectangle {
+                            x: px.min(x),
+                            y: py.min(y),
+                            w: (px - x).abs(),
+                            h: (py - y).abs(),
+                        });
+
+                        // disable animation
+                        context.animated_region = context.region;
+                    } else {
+                        match shift {
+                            true => {
+                                context.region = context
+                                    .snap
+                                    .windows
+                                    .iter()
+                                    .find(|w| w.bounds.contains(x as i32, y as i32))
+                                    .map(|w| Rectangle {
+                                        x: w.bounds.x as f64,
+                                        y: w.bounds.y as f64,
+                                        w: w.bounds.w as f64,
+                                        h: w.bounds.h as f64,
+                                    })
+                            }
+                            _ => context.region = None,
+                        }
+                    }
+                }
+            });
+
+            // so that the mouse shows where the keyboard is
+            if let Some((x, y)) = warp {
+                let _ = self
+                    .display
+                    .gl_window()
+                    .window()
+                    .set_cursor_position(LogicalPosition { x, y });
+            }
+
+            if cursor != shown_cursor {
+                self.display.gl_window().window().set_cursor(cursor);
+                shown_cursor = cursor;
+            }
+        }
+
+        self.display
+            .gl_window()
+            .window()
+            .set_cursor(MouseCursor::Default);
+        self.display.gl_window().window().hide();
+
+        if should_quit {
+            return Ok(Selection::Quit);
+        }
+
+        let region = match context.region {
+            Some(region) if region.w >= 1.0 && region.h >= 1.0 => Rectangle {
+                x: region.x as u32,
+                y: region.y as u32,
+                w: region.w as u32,
+                h: region.h as u32,
+            },
+            _ => return Ok(Selection::Cancelled),
+        };
+
+        Ok(match save {
+            true => Selection::Save(region),
+            false => Selection::Region(region),
+        })
+    }
+
+    fn render(
+        &mut self,
+        frame: &mut glium::Frame,
+        ctx: &mut CroppingContext,
+    ) -> Result<(), CropperError> {
+        let style = self.style;
+
+        if let (Some(areg), Some(reg)) = (ctx.animated_region, ctx.region) {
+            // don't overshoot on slow frames
+            let step = (ctx.delta.as_millis() as f64 / 1000.0 * style.animation_speed).min(1.0);
+
+            ctx.animated_region = Some(Rectangle {
+                x: areg.x + (reg.x - areg.x) * step,
+                y: areg.y + (reg.y - areg.y) * step,
+                w: areg.w + (reg.w - areg.w) * step,
+                h: areg.h + (reg.h - areg.h) * step,
+            });
+        } else {
+            ctx.animated_region = ctx.region;
+        }
+
+        let draw_params = DrawParameters {
+            blend: Blend::alpha_blending(),
+            ..Default::default()
+        };
+
+        // clear to the dim color
+        let [r, g, b] = style.dim_color;
+        frame.clear_color(r, g, b, 1.0);
+
+        // base pass
+        let uniforms = uniform! {
+            tex: &ctx.snap_tex,
+            opacity: easer::functions::Cubic::ease_out(
+                ctx.started.elapsed().as_millis().min(200) as f32,
+                1.0f32,
+                -style.dim_opacity,
+                200.0f32
+            ),
+        };
+
+        frame.draw(
+            &self.vbo,
+            &self.index_buffer,
+            &self.programs.full_quad_tex,
+            &uniforms,
+            &draw_params,
+        )?;
+
+        // active region pass
+        if let Some(areg) = ctx.animated_region {
+            if ctx.region_appear_time.is_none() {
+                ctx.region_appear_time = Some(Instant::now());
+            }
+
+            let uniforms = uniform! {
+                tex: &ctx.snap_tex,
+                opacity: easer::functions::Cubic::ease_out(
+                    ctx.region_appear_time.unwrap().elapsed().as_millis().min(200) as f32,
+                    1.0 - style.dim_opacity,
+                    style.dim_opacity,
+                    200.0f32
+                ),
+                bounds: quad_bounds(areg, ctx.snap),
+            };
+
+            frame.draw(
+                &self.vbo,
+                &self.index_buffer,
+                &self.programs.sub_quad_tex,
+                &uniforms,
+                &draw_params,
+            )?;
+
+            // handles pass
+            if ctx.adjusting {
+                for handle in Handle::ALL.iter() {
+                    let (x, y) = handle.center(areg);
+
+                    let uniforms = uniform! {
+                        color: [1.0f32, 1.0, 1.0, 1.0],
+                        border_color: [0.0f32, 0.0, 0.0, 1.0],
+                        border: (1.0 / HANDLE_SIZE) as f32,
+                        bounds: quad_bounds(
+                            Rectangle {
+                                x: (x - HANDLE_SIZE / 2.0).round(),
+                                y: (y - HANDLE_SIZE / 2.0).round(),
+                                w: HANDLE_SIZE,
+                                h: HANDLE_SIZE,
+                            },
+                            ctx.snap,
+                        ),
+                    };
+
+                    frame.draw(
+                        &self.vbo,
+                        &self.index_buffer,
+                        &self.programs.handle,
+                        &uniforms,
+                        &draw_params,
+                    )?;
+                }
+            }
+        } else {
+            ctx.region_appear_time = None;
+        }
+
+        // magnifier pass
+        if ctx.magnifier {
+            self.render_loupe(frame, ctx, &draw_params)?;
+        }
+
+        Ok(())
+    }
+
+    // the pixels around the cursor, bigger, with their coordinates below
+    fn render_loupe(
+        &self,
+        frame: &mut glium::Frame,
+        ctx: &mut CroppingContext,
+        draw_params: &DrawParameters,
+    ) -> Result<(), CropperError> {
+        let (x, y) = (ctx.cursor.0.floor(), ctx.cursor.1.floor());
+        let text = format!("{}, {}", x, y);
+
+        // only made again when the cursor goes to another pixel
+        if ctx.label.as_ref().is_none_or(|(shown, _)| *shown != text) {
+            let (data, size) = label_pixels(&text);
+
+            let texture = SrgbTexture2d::with_mipmaps(
+                &self.display,
+                RawImage2d::from_raw_rgba(data, size),
+                MipmapsOption::NoMipmap,
+            )?;
+
+            ctx.label = Some((text, texture));
+        }
+
+        let label = &ctx.label.as_ref().unwrap().1;
+        let label_w = label.width() as f64 * LABEL_SCALE;
+        let label_h = label.height() as f64 * LABEL_SCALE;
+
+        let side = LOUPE_PIXELS * LOUPE_ZOOM;
+        let height = side + label_h;
+
+        // the monitor under the cursor, so that the loupe doesn't straddle two
+        let screen = ctx
+            .snap
+            .monitors
+            .iter()
+            .map(|monitor| monitor.bounds)
+            .find(|bounds| bounds.contains(x as i32, y as i32))
+            .map(|bounds| Rectangle {
+                x: bounds.x as f64,
+                y: bounds.y as f64,
+                w: bounds.w as f64,
+                h: bounds.h as f64,
+            })
+            .unwrap_or(Rectangle {
+                x: 0.0,
+                y: 0.0,
+                w: ctx.snap.bounds.w as f64,
+                h: ctx.snap.bounds.h as f64,
+            });
+
+        // below and right of the cursor, or on the other side near the edges
+        let mut left = x + LOUPE_OFFSET;
+        let mut top = y + LOUPE_OFFSET;
+
+        if left + side > screen.x + screen.w {
+            left = x - LOUPE_OFFSET - side;
+        }
+        if top + height > screen.y + screen.h {
+            top = y - LOUPE_OFFSET - height;
+        }
+
+        let left = left.max(screen.x);
+        let top = top.max(screen.y);
+
+        let uniforms = uniform! {
+            tex: &ctx.snap_tex,
+            cursor: [x as f32, y as f32],
+            pixels: LOUPE_PIXELS as f32,
+            zoom: LOUPE_ZOOM as f32,
+            bounds: quad_bounds(
+                Rectangle {
+                    x: left,
+                    y: top,
+                    w: side,
+                    h: side,
+                },
+                ctx.snap,
+            ),
+        };
+
+        frame.draw(
+            &self.vbo,
+            &self.index_buffer,
+            &self.programs.loupe,
+            &uniforms,
+            draw_params,
+        )?;
+
+        let uniforms = uniform! {
+            tex: label,
+            bounds: quad_bounds(
+                Rectangle {
+                    x: left,
+                    y: top + side,
+                    w: label_w,
+                    h: label_h,
+                },
+                ctx.snap,
+            ),
+        };
+
+        frame.draw(
+            &self.vbo,
+            &self.index_buffer,
+            &self.programs.label,
+            &uniforms,
+            draw_params,
+        )?;
+
+        Ok(())
+    }
+}
+
+// 3x5 glyphs for the magnifier's coordinates, a bit per pixel from the top
+// left
+fn glyph(c: char) -> u16 {
+    match c {
+        '0' => 0b111_101_101_101_111,
+        '1' => 0b010_110_010_010_111,
+        '2' => 0b111_001_111_100_111,
+        '3' => 0b111_001_111_001_111,
+        '4' => 0b101_101_111_001_001,
+        '5' => 0b111_100_111_001_111,
+        '6' => 0b111_100_111_101_111,
+        '7' => 0b111_001_001_001_001,
+        '8' => 0b111_101_111_101_111,
+        '9' => 0b111_101_111_001_111,
+        '-' => 0b000_000_111_000_000,
+        ',' => 0b000_000_000_010_100,
+        _ => 0,
+    }
+}
+
+// white text on a dark background as RGBA, a texel per font pixel, rows from
+// the top
+fn label_pixels(text: &str) -> (Vec<u8>, (u32, u32)) {
+    const PADDING: usize = 2;
+
+    let chars: Vec<char> = text.chars().collect();
+    let width = chars.len() * 4 - 1 + PADDING * 2;
+    let height = 5 + PADDING * 2;
+
+    let mut data = [20u8, 20, 20, 220].repeat(width * height);
+
+    for (i, &c) in chars.iter().enumerate() {
+        let bits = glyph(c);
+
+        for row in 0..5 {
+            for col in 0..3 {
+                if bits >> (14 - (row * 3 + col)) & 1 == 1 {
+                    let x = PADDING + i * 4 + col;
+                    let y = PADDING + row;
+
+                    data[(y * width + x) * 4..][..4].copy_from_slice(&[255, 255, 255, 255]);
+                }
+            }
+        }
+    }
+
+    (data, (width as u32, height as u32))
+}
+
+// a region of the screenshot, as the shaders' bounds uniform
+fn quad_bounds(region: Rectangle<f64>, snap: &Screenshot) -> [f32; 4] {
+    [
+        (region.x as f32) / (snap.bounds.w as f32),
+        1.0 - (region.y as f32) / (snap.bounds.h as f32),
+        (region.w as f32) / (snap.bounds.w as f32),
+        -(region.h as f32) / (snap.bounds.h as f32),
+    ]
+}
@@ -0,0 +1,368 @@
+use super::{Action, Client, Message, Outcome, Request};
+use crate::{
+    image::Format,
+    screengrab::{CaptureMode, Rectangle},
+};
+
+use regex::Regex;
+use std::sync::mpsc;
+use zbus::{
+    blocking::{connection, Connection},
+    interface,
+    object_server::SignalEmitter,
+};
+
+const NAME: &str = "org.screensnap.Daemon";
+const PATH: &str = "/org/screensnap/Daemon";
+
+#[derive(Debug, zbus::DBusError)]
+#[zbus(prefix = "org.screensnap.Daemon.Error")]
+enum BusError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    Cancelled(String),
+    InvalidArgs(String),
+    Failed(String),
+}
+
+struct Daemon {
+    sender: mpsc::Sender<Message>,
+}
+
+impl Daemon {
+    // waits for the main thread, without blocking the other calls
+    async fn request(&self, request: Request) -> Result<(String, Vec<u8>), BusError> {
+        let (reply, replied) = mpsc::channel();
+
+        self.sender
+            .send(Message::Request(request, Client::Bus(reply)))
+            .map_err(|_| BusError::Failed(String::from("the daemon stopped")))?;
+
+        blocking::unblock(move || {
+            let outcome = replied
+                .recv()
+                .map_err(|_| BusError::Failed(String::from("the daemon stopped")))?
+                .map_err(BusError::Failed)?;
+
+            let (path, image) = match outcome {
+                Outcome::Captured { path, image } => (path, image),
+                Outcome::Cancelled | Outcome::Quit => {
+                    return Err(BusError::Cancelled(String::from(
+                        "the capture was cancelled",
+                    )))
+                }
+            };
+
+            let mut png = Vec::new();
+
+            if let Some(image) = image {
+                Format::Png
+                    .encoder(None)
+                    .encode(&image, &mut png)
+                    .map_err(|e| BusError::Failed(e.to_string()))?;
+            }
+
+            let path = path
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            Ok((path, png))
+        })
+        .await
+    }
+}
+
+// every method returns the path of the saved file (when the user saved with
+// Ctrl in the cropping window) or the PNG image, the other one is empty
+#[interface(name = "org.screensnap.Daemon")]
+impl Daemon {
+    async fn capture_interactive(&self) -> Result<(String, Vec<u8>), BusError> {
+        self.request(Request::Capture(Action::Crop)).await
+    }
+
+    // the topmost window whose title matches a regular expression
+    async fn capture_window(&self, title: &str) -> Result<(String, Vec<u8>), BusError> {
+        let pattern = Regex::new(title).map_err(|e| BusError::InvalidArgs(e.to_string()))?;
+
+        self.request(Request::Mode(CaptureMode::WindowTitle(pattern)))
+            .await
+    }
+
+    // relative to the virtual screen origin
+    async fn capture_region(
+        &self,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+    ) -> Result<(String, Vec<u8>), BusError> {
+        if w <= 0 || h <= 0 {
+            return Err(BusError::InvalidArgs(String::from(
+                "the width and height must be positive",
+            )));
+        }
+
+        self.request(Request::Mode(CaptureMode::Region(Rectangle { x, y, w, h })))
+            .await
+    }
+
+    // every capture, from hotkeys and clients too. the path is empty unless
+    // it was saved
+    #[zbus(signal)]
+    async fn captured(emitter: &SignalEmitter<'_>, path: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn cancelled(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+// keeps the service on the session bus
+pub struct Bus(Connection);
+
+pub fn serve(sender: mpsc::Sender<Message>) -> zbus::Result<Bus> {
+    serve_on(connection::Builder::session()?, sender)
+}
+
+fn serve_on(builder: connection::Builder<'_>, sender: mpsc::Sender<Message>) -> zbus::Result<Bus> {
+    let conn = builder
+        .name(NAME)?
+        .serve_at(PATH, Daemon { sender })?
+        .build()?;
+
+    Ok(Bus(conn))
+}
+
+impl Bus {
+    pub fn notify(&self, outcome: &Outcome) {
+        let emitter = match SignalEmitter::new(self.0.inner(), PATH) {
+            Ok(emitter) => emitter,
+            Err(_) => return,
+        };
+
+        let sent = match outcome {
+            Outcome::Captured { path, .. } => {
+                let path = path
+                    .as_ref()
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+
+                zbus::block_on(Daemon::captured(&emitter, &path))
+            }
+            Outcome::Cancelled => zbus::block_on(Daemon::cancelled(&emitter)),
+            Outcome::Quit => Ok(()),
+        };
+
+        if let Err(e) = sent {
+            if cfg!(debug_assertions) {
+                eprintln!("cannot signal the capture: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{image::Image, private_bus::PrivateBus};
+
+    use std::{fs, path::PathBuf, thread};
+    use zbus::blocking::Proxy;
+
+    // the service on a bus of its own, with the main thread answering every
+    // request with `answer`
+    struct Service {
+        bus: Bus,
+        client: Connection,
+        // what the main thread was asked
+        requests: mpsc::Receiver<Request>,
+        // last, the bus goes away with it
+        _private: PrivateBus,
+        dir: PathBuf,
+    }
+
+    impl Drop for Service {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn serve_answering(name: &str, answer: fn(&Request) -> Result<Outcome, String>) -> Service {
+        let dir = std::env::temp_dir().join(format!("screensnap-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let private = PrivateBus::start(&dir);
+        let (sender, receiver) = mpsc::channel();
+        let (asked, requests) = mpsc::channel();
+
+        thread::spawn(move || {
+            for message in receiver {
+                if let Message::Request(request, Client::Bus(reply)) = message {
+                    let _ = reply.send(answer(&request));
+                    let _ = asked.send(request);
+                }
+            }
+        });
+
+        Service {
+            bus: serve_on(private.builder(), sender).unwrap(),
+            client: private.connect(),
+            requests,
+            _private: private,
+            dir,
+        }
+    }
+
+    impl Service {
+        fn proxy(&self) -> Proxy<'_> {
+            Proxy::new(&self.client, NAME, PATH, NAME).unwrap()
+        }
+
+        fn call<B>(&self, method: &str, body: &B) -> zbus::Result<(String, Vec<u8>)>
+        where
+            B: serde::Serialize + zbus::zvariant::DynamicType,
+        {
+            self.proxy().call(method, body)
+        }
+    }
+
+    fn saved(_: &Request) -> Result<Outcome, String> {
+        Ok(Outcome::Captured {
+            path: Some(PathBuf::from("/tmp/shot.png")),
+            image: None,
+        })
+    }
+
+    fn given_back(_: &Request) -> Result<Outcome, String> {
+        Ok(Outcome::Captured {
+            path: None,
+            image: Some(Image {
+                width: 2,
+                height: 1,
+                data: vec![255, 0, 0, 0, 0, 255],
+            }),
+        })
+    }
+
+    fn cancelled(_: &Request) -> Result<Outcome, String> {
+        Ok(Outcome::Cancelled)
+    }
+
+    fn failed(_: &Request) -> Result<Outcome, String> {
+        Err(String::from("no window matches \"browser\""))
+    }
+
+    // the error's d-bus name
+    fn error_name(result: zbus::Result<(String, Vec<u8>)>) -> String {
+        match result {
+            Err(zbus::Error::MethodError(name, _, _)) => name.to_string(),
+            other => panic!("{:?} isn't an error from the service", other),
+        }
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon, run with --ignored"]
+    fn captures_interactively() {
+        let service = serve_answering("bus-interactive", saved);
+
+        assert_eq!(
+            service.call("CaptureInteractive", &()).unwrap(),
+            (String::from("/tmp/shot.png"), Vec::new())
+        );
+        assert!(matches!(
+            service.requests.recv().unwrap(),
+            Request::Capture(Action::Crop)
+        ));
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon, run with --ignored"]
+    fn captures_windows_by_title() {
+        let service = serve_answering("bus-window", given_back);
+
+        let (path, png) = service.call("CaptureWindow", &("^term",)).unwrap();
+        let decoder = png::Decoder::new(png.as_slice());
+        let info = decoder.read_info().unwrap().info().clone();
+
+        assert_eq!(path, "");
+        assert_eq!((info.width, info.height), (2, 1));
+        assert!(matches!(
+            service.requests.recv().unwrap(),
+            Request::Mode(CaptureMode::WindowTitle(pattern)) if pattern.as_str() == "^term"
+        ));
+
+        assert_eq!(
+            error_name(service.call("CaptureWindow", &("(",))),
+            "org.screensnap.Daemon.Error.InvalidArgs"
+        );
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon, run with --ignored"]
+    fn captures_regions() {
+        let service = serve_answering("bus-region", saved);
+
+        service.call("CaptureRegion", &(-10, 20, 30, 40)).unwrap();
+        assert!(matches!(
+            service.requests.recv().unwrap(),
+            Request::Mode(CaptureMode::Region(Rectangle {
+                x: -10,
+                y: 20,
+                w: 30,
+                h: 40
+            }))
+        ));
+
+        // too far is for the capture to clip, not for the bus to refuse
+        service
+            .call("CaptureRegion", &(i32::MAX, 0, i32::MAX, 10))
+            .unwrap();
+        assert!(matches!(
+            service.requests.recv().unwrap(),
+            Request::Mode(CaptureMode::Region(Rectangle { x: i32::MAX, .. }))
+        ));
+
+        for empty in [(0, 0, 0, 10), (0, 0, 10, -1)] {
+            assert_eq!(
+                error_name(service.call("CaptureRegion", &empty)),
+                "org.screensnap.Daemon.Error.InvalidArgs"
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon, run with --ignored"]
+    fn reports_cancellations_and_failures() {
+        assert_eq!(
+            error_name(serve_answering("bus-cancelled", cancelled).call("CaptureInteractive", &())),
+            "org.screensnap.Daemon.Error.Cancelled"
+        );
+        assert_eq!(
+            error_name(serve_answering("bus-failed", failed).call("CaptureWindow", &("browser",))),
+            "org.screensnap.Daemon.Error.Failed"
+        );
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon, run with --ignored"]
+    fn signals_every_capture() {
+        let service = serve_answering("bus-signals", saved);
+        let proxy = service.proxy();
+        let mut captured = proxy.receive_signal("Captured").unwrap();
+        let mut cancelled = proxy.receive_signal("Cancelled").unwrap();
+
+        service.bus.notify(&Outcome::Captured {
+            path: Some(PathBuf::from("/tmp/shot.png")),
+            image: None,
+        });
+        service.bus.notify(&Outcome::Captured {
+            path: None,
+            image: None,
+        });
+        service.bus.notify(&Outcome::Cancelled);
+
+        let path = |signal: zbus::message::Message| signal.body().deserialize::<String>().unwrap();
+
+        assert_eq!(path(captured.next().unwrap()), "/tmp/shot.png");
+        assert_eq!(path(captured.next().unwrap()), "");
+        assert!(cancelled.next().is_some());
+    }
+}
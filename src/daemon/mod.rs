@@ -0,0 +1,544 @@
+use super::{
+    config::{self, Config},
+    cropper::{Cropper, Selection, Style},
+    hotkey::{self, Binding, HotkeyError},
+    image::{Format, Image},
+    msgbox, save,
+    screengrab::{CaptureBackend, CaptureMode, Rectangle, Screenshot},
+    take, template, DaemonArgs, ScreenshotError,
+};
+
+use clap::ValueEnum;
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+};
+
+#[cfg(unix)]
+mod bus;
+
+// what a hotkey or a client asks for
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Action {
+    /// Crop to the clipboard (hold Ctrl to save to a file)
+    #[value(alias = "region")]
+    Crop,
+    /// Crop, then save to a file
+    #[value(alias = "region-to-file")]
+    CropToFile,
+    /// Copy the focused window
+    #[value(alias = "window")]
+    ActiveWindow,
+    /// Copy every monitor
+    Screen,
+    /// Copy the monitor under the mouse
+    #[value(alias = "monitor")]
+    CurrentMonitor,
+}
+
+impl Action {
+    // None when the user crops
+    fn mode(self) -> Option<CaptureMode> {
+        match self {
+            Action::Crop | Action::CropToFile => None,
+            Action::ActiveWindow => Some(CaptureMode::ActiveWindow),
+            Action::Screen => Some(CaptureMode::Screen),
+            Action::CurrentMonitor => Some(CaptureMode::CurrentMonitor),
+        }
+    }
+}
+
+// the daemon's bindings without --bind or [hotkeys]
+pub fn default_bindings() -> Vec<Binding<Action>> {
+    [
+        ("Print", Action::Crop),
+        ("Ctrl+Print", Action::ActiveWindow),
+        ("Shift+Print", Action::Screen),
+    ]
+    .iter()
+    .map(|&(chord, action)| Binding {
+        chord: chord.parse().unwrap(),
+        action,
+    })
+    .collect()
+}
+
+// what the daemon can be asked
+#[derive(Debug, Clone)]
+pub enum Request {
+    Capture(Action),
+    // a window or a region, without cropping (from d-bus)
+    Mode(CaptureMode),
+    // the same region (or window...) to the same place as last time
+    RepeatLast,
+    Quit,
+}
+
+// what clients can send on the socket, one line each: "capture region",
+// "repeat-last"...
+impl FromStr for Request {
+    type Err = String;
+
+    // "capture" can be left out
+    fn from_str(request: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = request.split_whitespace().collect();
+
+        match words[..] {
+            ["repeat-last"] => Ok(Request::RepeatLast),
+            ["quit"] => Ok(Request::Quit),
+            ["capture", action] | [action] => Action::from_str(action, true)
+                .map(Request::Capture)
+                .map_err(|_| format!("unknown request \"{}\"", request)),
+            _ => Err(format!("unknown request \"{}\"", request)),
+        }
+    }
+}
+
+// replies on the socket
+pub const REPLY_OK: &str = "ok";
+pub const REPLY_CANCELLED: &str = "cancelled";
+pub const REPLY_ERROR: &str = "error: ";
+
+// who asked, and how they hear back
+enum Client {
+    // errors go to a message box
+    Hotkey,
+    // a reply line
+    Socket(mpsc::Sender<String>),
+    // the image itself rather than the clipboard, errors as text
+    Bus(mpsc::Sender<Result<Outcome, String>>),
+}
+
+enum Message {
+    Request(Request, Client),
+    HotkeysFailed(HotkeyError),
+    ConfigChanged,
+}
+
+enum Outcome {
+    // the path when it was saved, the image when it was asked for
+    Captured {
+        path: Option<PathBuf>,
+        image: Option<Image>,
+    },
+    Cancelled,
+    Quit,
+}
+
+// where the last capture went, for repeat-last
+struct Last {
+    mode: CaptureMode,
+    save: bool,
+}
+
+// the config with the command line on top
+struct Settings {
+    style: Style,
+    bindings: Vec<Binding<Action>>,
+    output: String,
+    format: Option<Format>,
+}
+
+impl Settings {
+    fn new(config: Config, args: &DaemonArgs) -> Self {
+        let bindings = match args.bindings.is_empty() {
+            true => config.bindings.unwrap_or_else(default_bindings),
+            false => args.bindings.clone(),
+        };
+
+        // a path from the command line goes by its extension
+        let (output, format) = match &args.output {
+            Some(output) => (output.clone(), None),
+            None => (
+                config
+                    .output
+                    .unwrap_or_else(|| template::DEFAULT.to_owned()),
+                config.format,
+            ),
+        };
+
+        Settings {
+            style: config.style,
+            bindings,
+            output,
+            format,
+        }
+    }
+}
+
+// the thread waiting for hotkeys, restarted when the bindings change
+struct Hotkeys {
+    stop: Arc<AtomicBool>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl Hotkeys {
+    // hotkeys pressed while `busy` are dropped
+    fn start(
+        bindings: Vec<Binding<Action>>,
+        sender: mpsc::Sender<Message>,
+        busy: Arc<AtomicBool>,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+
+        let thread = thread::spawn(move || {
+            let result = hotkey::register(&bindings, true, &stopped, |binding| {
+                if busy.load(Ordering::Relaxed) {
+                    return false;
+                }
+
+                // stop once the daemon is gone
+                let request = Request::Capture(binding.action);
+                sender
+                    .send(Message::Request(request, Client::Hotkey))
+                    .is_err()
+            });
+
+            if let Err(e) = result {
+                let _ = sender.send(Message::HotkeysFailed(e));
+            }
+        });
+
+        Hotkeys { stop, thread }
+    }
+
+    // returns once the keys are released, so they can be grabbed again
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.thread.join();
+    }
+}
+
+// waits for hotkeys and clients, and captures on the main thread, where the
+// cropper's GL context lives
+pub fn run(
+    args: &DaemonArgs,
+    config: Config,
+    backends: &[Box<dyn CaptureBackend>],
+) -> Result<(), ScreenshotError> {
+    let mut settings = Settings::new(config, args);
+
+    // create the cropper
+    let mut cropper = Cropper::new()?;
+    cropper.set_style(settings.style);
+
+    let (sender, receiver) = mpsc::channel();
+
+    // hotkeys pressed while capturing are dropped
+    let busy = Arc::new(AtomicBool::new(false));
+
+    let mut hotkeys = Hotkeys::start(settings.bindings.clone(), sender.clone(), busy.clone());
+
+    // changes to config.toml apply without restarting
+    if let Some(path) = config::path() {
+        let sender = sender.clone();
+
+        if let Err(e) = config::watch(&path, move || sender.send(Message::ConfigChanged).is_err()) {
+            eprintln!("cannot watch {}: {}", path.display(), e);
+        }
+    }
+
+    // desktop integrations call org.screensnap.Daemon
+    #[cfg(unix)]
+    let bus = bus::serve(sender.clone())
+        .map_err(|e| eprintln!("cannot serve d-bus requests: {}", e))
+        .ok();
+
+    // wayland compositors don't let us grab keys, they bind them to
+    // `screensnap trigger` instead
+    #[cfg(unix)]
+    let requests = sender.clone();
+    #[cfg(unix)]
+    let listener = super::ipc::listen(move |line| {
+        let request = match line.parse::<Request>() {
+            Ok(request) => request,
+            Err(e) => return format!("{}{}", REPLY_ERROR, e),
+        };
+
+        let (reply, replied) = mpsc::channel();
+        let _ = requests.send(Message::Request(request, Client::Socket(reply)));
+
+        replied
+            .recv()
+            .unwrap_or_else(|_| format!("{}the daemon stopped", REPLY_ERROR))
+    })
+    .map_err(|e| eprintln!("cannot listen for clients: {}", e))
+    .ok();
+
+    #[cfg(unix)]
+    let listening = listener.is_some() || bus.is_some();
+    #[cfg(not(unix))]
+    let listening = false;
+
+    let mut last = None;
+
+    // once reloaded, the config can fix hotkeys that failed
+    let mut reloaded = false;
+
+    for message in receiver {
+        let (request, client) = match message {
+            Message::Request(request, client) => (request, client),
+            // clients can still reach us
+            Message::HotkeysFailed(e) if listening || reloaded => {
+                msgbox::error(&e.to_string());
+                continue;
+            }
+            Message::HotkeysFailed(e) => return Err(e.into()),
+
+            // a broken config keeps the previous one
+            Message::ConfigChanged => {
+                match config::load() {
+                    Ok(config) => {
+                        let changed = Settings::new(config, args);
+                        cropper.set_style(changed.style);
+
+                        if changed.bindings != settings.bindings {
+                            hotkeys.stop();
+                            hotkeys = Hotkeys::start(
+                                changed.bindings.clone(),
+                                sender.clone(),
+                                busy.clone(),
+                            );
+                        }
+
+                        settings = changed;
+                        reloaded = true;
+
+                        if cfg!(debug_assertions) {
+                            eprintln!("reloaded the config");
+                        }
+                    }
+                    Err(e) => msgbox::error(&e.to_string()),
+                }
+
+                continue;
+            }
+        };
+
+        // d-bus wants the image, the others have it copied
+        let give_back = matches!(client, Client::Bus(_));
+
+        busy.store(true, Ordering::Relaxed);
+        let result = handle(
+            request,
+            give_back,
+            &mut cropper,
+            backends,
+            &settings,
+            &mut last,
+        );
+        busy.store(false, Ordering::Relaxed);
+
+        #[cfg(unix)]
+        if let (Some(bus), Ok(outcome)) = (&bus, &result) {
+            bus.notify(outcome);
+        }
+
+        let quit = matches!(result, Ok(Outcome::Quit));
+
+        // the cropper is still fine after these, try again next time
+        let fatal = !matches!(
+            result,
+            Ok(_)
+                | Err(ScreenshotError::Capture { .. })
+                | Err(ScreenshotError::Mode { .. })
+                | Err(ScreenshotError::Output { .. })
+                | Err(ScreenshotError::Encoding { .. })
+                | Err(ScreenshotError::NothingToRepeat)
+        );
+
+        match (client, result) {
+            (Client::Hotkey, Err(e)) if !fatal => msgbox::error(&e.to_string()),
+            (Client::Hotkey, Err(e)) => return Err(e),
+            (Client::Hotkey, Ok(_)) => (),
+
+            (Client::Socket(reply), result) => {
+                let _ = reply.send(match &result {
+                    Ok(Outcome::Cancelled) => REPLY_CANCELLED.to_owned(),
+                    Ok(_) => REPLY_OK.to_owned(),
+                    Err(e) => format!("{}{}", REPLY_ERROR, e),
+                });
+
+                if fatal {
+                    return result.map(|_| ());
+                }
+            }
+
+            (Client::Bus(reply), Err(e)) => {
+                let _ = reply.send(Err(e.to_string()));
+
+                if fatal {
+                    return Err(e);
+                }
+            }
+            (Client::Bus(reply), Ok(outcome)) => {
+                let _ = reply.send(Ok(outcome));
+            }
+        }
+
+        if quit {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn handle(
+    request: Request,
+    give_back: bool,
+    cropper: &mut Cropper,
+    backends: &[Box<dyn CaptureBackend>],
+    settings: &Settings,
+    last: &mut Option<Last>,
+) -> Result<Outcome, ScreenshotError> {
+    let action = match request {
+        Request::Capture(action) => action,
+        Request::Quit => return Ok(Outcome::Quit),
+
+        Request::Mode(mode) => {
+            let snap = take(backends)?;
+            let outcome = deliver_mode(&snap, &mode, false, give_back, settings)?;
+
+            *last = Some(Last { mode, save: false });
+
+            return Ok(outcome);
+        }
+
+        Request::RepeatLast => {
+            let Last {
+                mode,
+                save: to_file,
+            } = last.as_ref().ok_or(ScreenshotError::NothingToRepeat)?;
+
+            let snap = take(backends)?;
+
+            return deliver_mode(&snap, mode, *to_file, give_back, settings);
+        }
+    };
+
+    let snap = take(backends)?;
+
+    let selection = match action.mode() {
+        Some(mode) => Selection::Region(mode.region(&snap)?),
+        None => match (cropper.apply(&snap)?, action) {
+            (Selection::Region(region), Action::CropToFile) => Selection::Save(region),
+            (selection, _) => selection,
+        },
+    };
+
+    let (region, to_file) = match selection {
+        Selection::Region(region) => (region, false),
+        Selection::Save(region) => (region, true),
+        Selection::Cancelled => return Ok(Outcome::Cancelled),
+        Selection::Quit => return Ok(Outcome::Quit),
+    };
+
+    // the cropped region, or the window (monitor...) again next time
+    *last = Some(Last {
+        mode: action.mode().unwrap_or(CaptureMode::Region(Rectangle {
+            x: region.x as i32,
+            y: region.y as i32,
+            w: region.w as i32,
+            h: region.h as i32,
+        })),
+        save: to_file,
+    });
+
+    deliver(&snap, region, to_file, give_back, settings)
+}
+
+// like deliver, but an image given back is cropped by the mode (whole
+// monitors...)
+fn deliver_mode(
+    snap: &Screenshot,
+    mode: &CaptureMode,
+    to_file: bool,
+    give_back: bool,
+    settings: &Settings,
+) -> Result<Outcome, ScreenshotError> {
+    if give_back && !to_file {
+        return Ok(Outcome::Captured {
+            path: None,
+            image: Some(mode.crop(snap)?),
+        });
+    }
+
+    deliver(snap, mode.region(snap)?, to_file, give_back, settings)
+}
+
+// saves the region, copies it to the clipboard, or gives it back
+fn deliver(
+    snap: &Screenshot,
+    region: Rectangle<u32>,
+    to_file: bool,
+    give_back: bool,
+    settings: &Settings,
+) -> Result<Outcome, ScreenshotError> {
+    let (path, image) = match (to_file, give_back) {
+        (true, _) => (
+            save(snap, region, &settings.output, settings.format, None)?,
+            None,
+        ),
+        (false, true) => (None, Some(snap.crop(region))),
+
+        // copy to clipboard!
+        (false, false) => {
+            snap.copy_to_clipboard(region, None);
+            (None, None)
+        }
+    };
+
+    Ok(Outcome::Captured { path, image })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Request, String> {
+        line.parse()
+    }
+
+    #[test]
+    fn parses_requests() {
+        assert!(matches!(parse("repeat-last"), Ok(Request::RepeatLast)));
+        assert!(matches!(parse(" quit\n"), Ok(Request::Quit)));
+        assert!(matches!(
+            parse("capture crop-to-file"),
+            Ok(Request::Capture(Action::CropToFile))
+        ));
+
+        // "capture" is optional, and the aliases work too
+        assert!(matches!(
+            parse("screen"),
+            Ok(Request::Capture(Action::Screen))
+        ));
+        assert!(matches!(
+            parse("capture  region"),
+            Ok(Request::Capture(Action::Crop))
+        ));
+        assert!(matches!(
+            parse("Window"),
+            Ok(Request::Capture(Action::ActiveWindow))
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_requests() {
+        for bad in ["", "capture", "capture everything", "quit now", "repeat"] {
+            assert_eq!(
+                parse(bad).err(),
+                Some(format!("unknown request \"{}\"", bad)),
+                "{:?}",
+                bad
+            );
+        }
+    }
+}
@@ -1,4 +1,5 @@
 #[cfg_attr(windows, path = "windows.rs")]
+#[cfg_attr(unix, path = "x11.rs")]
 mod os;
 
 pub use os::focus_current_window;
@@ -0,0 +1,72 @@
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{
+        AtomEnum, ClientMessageEvent, ConnectionExt as _, EventMask, Window, CLIENT_MESSAGE_EVENT,
+    },
+    rust_connection::RustConnection,
+};
+
+fn intern(conn: &RustConnection, name: &[u8]) -> Option<u32> {
+    conn.intern_atom(false, name)
+        .ok()?
+        .reply()
+        .ok()
+        .map(|reply| reply.atom)
+}
+
+fn get_process_window(conn: &RustConnection, root: Window, pid: u32) -> Option<Window> {
+    let net_client_list = intern(conn, b"_NET_CLIENT_LIST")?;
+    let net_wm_pid = intern(conn, b"_NET_WM_PID")?;
+
+    let clients = conn
+        .get_property(false, root, net_client_list, AtomEnum::WINDOW, 0, u32::MAX)
+        .ok()?
+        .reply()
+        .ok()?;
+
+    // iterate over managed windows
+    let wnd = clients.value32()?.find(|&wnd| {
+        conn.get_property(false, wnd, net_wm_pid, AtomEnum::CARDINAL, 0, 1)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .and_then(|reply| reply.value32().and_then(|mut v| v.next()))
+            == Some(pid)
+    });
+
+    wnd
+}
+
+pub fn focus_current_window() {
+    let (conn, screen_num) = match x11rb::connect(None) {
+        Ok(c) => c,
+        Err(_) => return,
+    };
+
+    let root = conn.setup().roots[screen_num].root;
+
+    let (wnd, net_active_window) = match (
+        get_process_window(&conn, root, std::process::id()),
+        intern(&conn, b"_NET_ACTIVE_WINDOW"),
+    ) {
+        (Some(wnd), Some(atom)) => (wnd, atom),
+        _ => return,
+    };
+
+    // ask the window manager to activate it (source indication 2 = pager)
+    let event = ClientMessageEvent {
+        response_type: CLIENT_MESSAGE_EVENT,
+        format: 32,
+        sequence: 0,
+        window: wnd,
+        type_: net_active_window,
+        data: [2, x11rb::CURRENT_TIME, 0, 0, 0].into(),
+    };
+
+    let _ = conn.send_event(
+        false,
+        root,
+        EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+        event,
+    );
+    let _ = conn.flush();
+}
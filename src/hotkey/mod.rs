@@ -1,4 +1,106 @@
-#[cfg_attr(windows, path = "windows.rs")]
-mod os;
-
-pub use os::register;
+use custom_error::custom_error;
+use std::{fmt, str::FromStr};
+
+#[cfg_attr(windows, path = "windows.rs")]
+#[cfg_attr(unix, path = "x11.rs")]
+mod os;
+
+// calls back on every binding pressed, until the callback returns true or
+// `stop` is set
+pub use os::register;
+
+custom_error! { pub HotkeyError
+    Unavailable{reason: String} = "cannot listen to the keyboard: {reason}",
+    NoKey{chord: Chord} = "no key on the keyboard is {chord}",
+    AlreadyGrabbed{chord: Chord} = "{chord} is already used by another program",
+}
+
+// a key that can be bound, named like on the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Print,
+    // A to Z, 0 to 9
+    Char(char),
+    // F1 to F24
+    Function(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    // the Windows/logo key
+    pub logo: bool,
+}
+
+// a key with modifiers, like "Ctrl+Print"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    pub modifiers: Modifiers,
+    pub key: Key,
+}
+
+// what the callback gets when a chord is pressed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding<A> {
+    pub chord: Chord,
+    pub action: A,
+}
+
+impl FromStr for Chord {
+    type Err = String;
+
+    // modifiers first, in any order: "Super+Shift+S"
+    fn from_str(chord: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = Modifiers::default();
+        let mut parts: Vec<&str> = chord.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default();
+
+        for part in parts {
+            let modifier = match part.to_lowercase().as_str() {
+                "ctrl" | "control" => &mut modifiers.ctrl,
+                "shift" => &mut modifiers.shift,
+                "alt" => &mut modifiers.alt,
+                "super" | "win" | "logo" => &mut modifiers.logo,
+                _ => return Err(format!("unknown modifier \"{}\"", part)),
+            };
+
+            *modifier = true;
+        }
+
+        let key = match key.to_lowercase().as_str() {
+            "print" | "printscreen" | "sysrq" | "snapshot" => Key::Print,
+            name if name.len() == 1 && name.chars().all(|c| c.is_ascii_alphanumeric()) => {
+                Key::Char(name.chars().next().unwrap().to_ascii_uppercase())
+            }
+            name => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n @ 1..=24) => Key::Function(n),
+                _ => return Err(format!("unknown key \"{}\"", key)),
+            },
+        };
+
+        Ok(Chord { modifiers, key })
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let modifiers = [
+            (self.modifiers.ctrl, "Ctrl+"),
+            (self.modifiers.shift, "Shift+"),
+            (self.modifiers.alt, "Alt+"),
+            (self.modifiers.logo, "Super+"),
+        ];
+
+        for (_, name) in modifiers.iter().filter(|(held, _)| *held) {
+            f.write_str(name)?;
+        }
+
+        match self.key {
+            Key::Print => f.write_str("Print Screen"),
+            Key::Char(c) => write!(f, "{}", c),
+            Key::Function(n) => write!(f, "F{}", n),
+        }
+    }
+}
@@ -1,27 +1,97 @@
-use std::{mem::MaybeUninit, ptr::null_mut};
-
-use winapi::um::winuser::{
-    GetMessageW, PeekMessageW, RegisterHotKey, PM_REMOVE, VK_SNAPSHOT, WM_HOTKEY,
-};
-
-pub fn register<T>(consume_queue: bool, mut callback: T)
-where
-    T: FnMut() -> bool,
-{
-    let mut msg = unsafe { MaybeUninit::uninit().assume_init() };
-
-    unsafe {
-        RegisterHotKey(null_mut(), 1, 0, VK_SNAPSHOT as u32);
-    }
-
-    while unsafe { GetMessageW(&mut msg, null_mut(), WM_HOTKEY, WM_HOTKEY) } != 0 {
-        if callback() {
-            break;
-        } else if consume_queue {
-            // consume all hotkey events
-            while unsafe { PeekMessageW(&mut msg, null_mut(), WM_HOTKEY, WM_HOTKEY, PM_REMOVE) }
-                != 0
-            {}
-        }
-    }
-}
+use super::{Binding, HotkeyError, Key};
+
+use std::{
+    mem::MaybeUninit,
+    ptr::null_mut,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use winapi::um::winuser::{
+    MsgWaitForMultipleObjects, PeekMessageW, RegisterHotKey, UnregisterHotKey, MOD_ALT,
+    MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN, PM_REMOVE, QS_HOTKEY, VK_F1, VK_SNAPSHOT,
+    WM_HOTKEY,
+};
+
+// how often `stop` is checked, in milliseconds
+const STOP_POLL_MS: u32 = 200;
+
+pub fn register<A, T>(
+    bindings: &[Binding<A>],
+    consume_queue: bool,
+    stop: &AtomicBool,
+    mut callback: T,
+) -> Result<(), HotkeyError>
+where
+    T: FnMut(&Binding<A>) -> bool,
+{
+    let mut msg = unsafe { MaybeUninit::uninit().assume_init() };
+
+    // the hotkey ids are the binding indices + 1
+    for (i, binding) in bindings.iter().enumerate() {
+        let modifiers = binding.chord.modifiers;
+        let mut flags = MOD_NOREPEAT;
+
+        if modifiers.ctrl {
+            flags |= MOD_CONTROL;
+        }
+        if modifiers.shift {
+            flags |= MOD_SHIFT;
+        }
+        if modifiers.alt {
+            flags |= MOD_ALT;
+        }
+        if modifiers.logo {
+            flags |= MOD_WIN;
+        }
+
+        // letters and digits are their own virtual-key codes
+        let vk = match binding.chord.key {
+            Key::Print => VK_SNAPSHOT as u32,
+            Key::Char(c) => c as u32,
+            Key::Function(n) => (VK_F1 + n as i32 - 1) as u32,
+        };
+
+        // fails when another program registered it first
+        if unsafe { RegisterHotKey(null_mut(), i as i32 + 1, flags as u32, vk) } == 0 {
+            unregister(i);
+
+            return Err(HotkeyError::AlreadyGrabbed {
+                chord: binding.chord,
+            });
+        }
+    }
+
+    while !stop.load(Ordering::Relaxed) {
+        // wait a little for a hotkey, then look at `stop` again
+        if unsafe { PeekMessageW(&mut msg, null_mut(), WM_HOTKEY, WM_HOTKEY, PM_REMOVE) } == 0 {
+            unsafe { MsgWaitForMultipleObjects(0, null_mut(), 0, STOP_POLL_MS, QS_HOTKEY) };
+            continue;
+        }
+
+        let binding = match bindings.get(msg.wParam.wrapping_sub(1)) {
+            Some(binding) => binding,
+            None => continue,
+        };
+
+        if callback(binding) {
+            break;
+        } else if consume_queue {
+            // consume all hotkey events
+            while unsafe { PeekMessageW(&mut msg, null_mut(), WM_HOTKEY, WM_HOTKEY, PM_REMOVE) }
+                != 0
+            {}
+        }
+    }
+
+    // they would stay registered to the thread until the process exits
+    unregister(bindings.len());
+
+    Ok(())
+}
+
+// unregisters the first `count` hotkeys
+fn unregister(count: usize) {
+    for i in 0..count {
+        unsafe { UnregisterHotKey(null_mut(), i as i32 + 1) };
+    }
+}
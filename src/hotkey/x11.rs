@@ -0,0 +1,321 @@
+use super::{Binding, HotkeyError, Key, Modifiers};
+
+use x11rb::{
+    connection::Connection,
+    errors::ReplyError,
+    protocol::{
+        xproto::{ConnectionExt as _, GrabMode, Keycode, ModMask},
+        ErrorKind, Event,
+    },
+    rust_connection::RustConnection,
+};
+
+use std::{
+    os::unix::io::AsRawFd,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+// how often `stop` is checked, in milliseconds
+const STOP_POLL_MS: i32 = 200;
+
+// keysyms for Print Screen, F1 and Num Lock (letters and digits are ASCII)
+const XK_PRINT: u32 = 0xff61;
+const XK_F1: u32 = 0xffbe;
+const XK_NUM_LOCK: u32 = 0xff7f;
+
+pub fn register<A, T>(
+    bindings: &[Binding<A>],
+    consume_queue: bool,
+    stop: &AtomicBool,
+    mut callback: T,
+) -> Result<(), HotkeyError>
+where
+    T: FnMut(&Binding<A>) -> bool,
+{
+    let unavailable = |e: &dyn std::fmt::Display| HotkeyError::Unavailable {
+        reason: e.to_string(),
+    };
+
+    let (conn, screen_num) = x11rb::connect(None).map_err(|e| unavailable(&e))?;
+    let root = conn.setup().roots[screen_num].root;
+
+    // grabs only match the exact modifiers, so the keys would stop working
+    // with Num Lock or Caps Lock on
+    let num_lock = num_lock_mask(&conn).map_err(|e| unavailable(&e))?;
+    let locks = [
+        ModMask::from(0u16),
+        ModMask::LOCK,
+        num_lock,
+        num_lock | ModMask::LOCK,
+    ];
+
+    // the keycode and modifiers of every binding
+    let mut grabs = Vec::with_capacity(bindings.len());
+
+    for binding in bindings {
+        let keycode = keycode(&conn, keysym(binding.chord.key))
+            .map_err(|e| unavailable(&e))?
+            .ok_or(HotkeyError::NoKey {
+                chord: binding.chord,
+            })?;
+        let modifiers = mod_mask(binding.chord.modifiers);
+
+        for &lock in &locks {
+            let grab = conn
+                .grab_key(
+                    true,
+                    root,
+                    modifiers | lock,
+                    keycode,
+                    GrabMode::ASYNC,
+                    GrabMode::ASYNC,
+                )
+                .map_err(|e| unavailable(&e))?
+                .check();
+
+            match grab {
+                Ok(()) => (),
+                // someone else (usually the desktop environment) has it
+                Err(ReplyError::X11Error(e)) if e.error_kind == ErrorKind::Access => {
+                    return Err(HotkeyError::AlreadyGrabbed {
+                        chord: binding.chord,
+                    })
+                }
+                Err(e) => return Err(unavailable(&e)),
+            }
+        }
+
+        grabs.push((keycode, u16::from(modifiers)));
+    }
+
+    // the modifiers bindings care about, without the locks
+    let relevant = u16::from(mod_mask(Modifiers {
+        ctrl: true,
+        shift: true,
+        alt: true,
+        logo: true,
+    }));
+
+    // the grabs go away with the connection
+    while !stop.load(Ordering::Relaxed) {
+        let event = match conn.poll_for_event().map_err(|e| unavailable(&e))? {
+            Some(event) => event,
+            None => {
+                wait_readable(&conn);
+                continue;
+            }
+        };
+
+        let pressed = match event {
+            Event::KeyPress(e) => grabs
+                .iter()
+                .position(|&grab| grab == (e.detail, u16::from(e.state) & relevant)),
+            _ => None,
+        };
+
+        if let Some(i) = pressed {
+            if callback(&bindings[i]) {
+                break;
+            } else if consume_queue {
+                // consume all hotkey events
+                while let Ok(Some(_)) = conn.poll_for_event() {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// waits for the server to send something, but not long so that `stop` is seen
+fn wait_readable(conn: &RustConnection) {
+    let mut fd = libc::pollfd {
+        fd: conn.stream().as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    unsafe { libc::poll(&mut fd, 1, STOP_POLL_MS) };
+}
+
+fn keysym(key: Key) -> u32 {
+    match key {
+        Key::Print => XK_PRINT,
+        // the unshifted keysym, so lowercase
+        Key::Char(c) => c.to_ascii_lowercase() as u32,
+        Key::Function(n) => XK_F1 + n as u32 - 1,
+    }
+}
+
+// Super is Mod4 on pretty much every keyboard layout
+fn mod_mask(modifiers: Modifiers) -> ModMask {
+    let mut mask = ModMask::from(0u16);
+
+    if modifiers.ctrl {
+        mask |= ModMask::CONTROL;
+    }
+    if modifiers.shift {
+        mask |= ModMask::SHIFT;
+    }
+    if modifiers.alt {
+        mask |= ModMask::M1;
+    }
+    if modifiers.logo {
+        mask |= ModMask::M4;
+    }
+
+    mask
+}
+
+fn keycode(conn: &RustConnection, keysym: u32) -> Result<Option<Keycode>, ReplyError> {
+    let min_keycode = conn.setup().min_keycode;
+    let max_keycode = conn.setup().max_keycode;
+
+    let mapping = conn
+        .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?
+        .reply()?;
+
+    Ok(mapping
+        .keysyms
+        .chunks(mapping.keysyms_per_keycode as usize)
+        .position(|syms| syms.contains(&keysym))
+        .map(|i| min_keycode + i as u8))
+}
+
+// the modifier Num Lock is mapped to, usually Mod2
+fn num_lock_mask(conn: &RustConnection) -> Result<ModMask, ReplyError> {
+    let num_lock = match keycode(conn, XK_NUM_LOCK)? {
+        Some(keycode) => keycode,
+        None => return Ok(ModMask::from(0u16)),
+    };
+
+    let mapping = conn.get_modifier_mapping()?.reply()?;
+    let per_modifier = mapping.keycodes_per_modifier() as usize;
+
+    // the keycodes come in 8 groups: Shift, Lock, Control, Mod1...Mod5
+    Ok(mapping
+        .keycodes
+        .chunks(per_modifier.max(1))
+        .position(|keycodes| keycodes.contains(&num_lock))
+        .map(|i| ModMask::from(1u16 << i))
+        .unwrap_or_else(|| ModMask::from(0u16)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hotkey::Chord;
+
+    use std::{
+        sync::{mpsc, Arc},
+        thread,
+        time::Duration,
+    };
+    use x11rb::{
+        protocol::{
+            xproto::{KEY_PRESS_EVENT, KEY_RELEASE_EVENT},
+            xtest::ConnectionExt as _,
+        },
+        wrapper::ConnectionExt as _,
+    };
+
+    const XK_CAPS_LOCK: u32 = 0xffe5;
+
+    fn print() -> Binding<()> {
+        Binding {
+            chord: "Print".parse::<Chord>().unwrap(),
+            action: (),
+        }
+    }
+
+    // presses and releases a key, like the user would
+    fn tap(conn: &RustConnection, keysym: u32) {
+        let keycode = keycode(conn, keysym).unwrap().unwrap();
+        let root = conn.setup().roots[0].root;
+
+        for type_ in [KEY_PRESS_EVENT, KEY_RELEASE_EVENT] {
+            conn.xtest_fake_input(type_, keycode, 0, root, 0, 0, 0)
+                .unwrap()
+                .check()
+                .unwrap();
+        }
+
+        conn.sync().unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs an X server with XTest, run with `xvfb-run cargo test -- --ignored`"]
+    fn fires_with_and_without_locks() {
+        let (conn, _) = x11rb::connect(None).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let (pressed, presses) = mpsc::channel();
+
+        let listener = {
+            let stop = stop.clone();
+
+            thread::spawn(move || {
+                register(&[print()], false, &stop, |binding| {
+                    pressed.send(binding.chord).unwrap();
+                    false
+                })
+            })
+        };
+
+        // the grab is in place once Print gets through
+        let grabbed = (0..25).any(|_| {
+            tap(&conn, XK_PRINT);
+            presses.recv_timeout(Duration::from_millis(200)).is_ok()
+        });
+        assert!(grabbed, "Print was never grabbed");
+        while presses.recv_timeout(Duration::from_millis(200)).is_ok() {}
+
+        // none, Num Lock, Num Lock and Caps Lock, Caps Lock
+        for lock in [
+            None,
+            Some(XK_NUM_LOCK),
+            Some(XK_CAPS_LOCK),
+            Some(XK_NUM_LOCK),
+        ] {
+            if let Some(lock) = lock {
+                tap(&conn, lock);
+            }
+
+            tap(&conn, XK_PRINT);
+
+            let chord = presses.recv_timeout(Duration::from_secs(2));
+            assert_eq!(chord, Ok(print().chord), "after toggling {:?}", lock);
+        }
+
+        // leave the locks as they were
+        tap(&conn, XK_CAPS_LOCK);
+
+        stop.store(true, Ordering::Relaxed);
+        listener.join().unwrap().unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs an X server with XTest, run with `xvfb-run cargo test -- --ignored`"]
+    fn reports_keys_grabbed_elsewhere() {
+        let (conn, screen_num) = x11rb::connect(None).unwrap();
+        let root = conn.setup().roots[screen_num].root;
+        let print_key = keycode(&conn, XK_PRINT).unwrap().unwrap();
+
+        conn.grab_key(
+            true,
+            root,
+            ModMask::from(0u16),
+            print_key,
+            GrabMode::ASYNC,
+            GrabMode::ASYNC,
+        )
+        .unwrap()
+        .check()
+        .unwrap();
+
+        let result = register(&[print()], false, &AtomicBool::new(false), |_| true);
+
+        assert!(matches!(
+            result,
+            Err(HotkeyError::AlreadyGrabbed { chord }) if chord == print().chord
+        ));
+    }
+}
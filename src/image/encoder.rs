@@ -0,0 +1,300 @@
+use super::Image;
+
+use custom_error::custom_error;
+use std::io::{self, Write};
+
+custom_error! { pub EncodingError
+    Io{source: io::Error} = "{source}",
+    Png{source: png::EncodingError} = "{source}",
+    Jpeg{source: jpeg_encoder::EncodingError} = "{source}",
+    Qoi{source: qoi::Error} = "{source}",
+    WebP{reason: String} = "{reason}",
+    TooLarge{max: u32} = "the format doesn't go over {max} pixels per side",
+}
+
+// writes images in some format
+pub trait Encoder {
+    fn encode(&self, image: &Image, out: &mut dyn Write) -> Result<(), EncodingError>;
+}
+
+pub struct Png;
+
+impl Encoder for Png {
+    fn encode(&self, image: &Image, out: &mut dyn Write) -> Result<(), EncodingError> {
+        let mut encoder = png::Encoder::new(out, image.width, image.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&image.data)?;
+        writer.finish()?;
+
+        Ok(())
+    }
+}
+
+pub struct Jpeg {
+    pub quality: u8,
+}
+
+impl Encoder for Jpeg {
+    fn encode(&self, image: &Image, out: &mut dyn Write) -> Result<(), EncodingError> {
+        let max = u16::MAX as u32;
+
+        if image.width > max || image.height > max {
+            return Err(EncodingError::TooLarge { max });
+        }
+
+        jpeg_encoder::Encoder::new(out, self.quality.clamp(1, 100)).encode(
+            &image.data,
+            image.width as u16,
+            image.height as u16,
+            jpeg_encoder::ColorType::Rgb,
+        )?;
+
+        Ok(())
+    }
+}
+
+pub struct WebP {
+    // lossless without one
+    pub quality: Option<u8>,
+}
+
+impl Encoder for WebP {
+    fn encode(&self, image: &Image, out: &mut dyn Write) -> Result<(), EncodingError> {
+        let max = 16383;
+
+        if image.width > max || image.height > max {
+            return Err(EncodingError::TooLarge { max });
+        }
+
+        let encoded = webp::Encoder::from_rgb(&image.data, image.width, image.height)
+            .encode_simple(
+                self.quality.is_none(),
+                self.quality.unwrap_or(100).clamp(1, 100) as f32,
+            )
+            .map_err(|e| EncodingError::WebP {
+                reason: format!("{:?}", e),
+            })?;
+
+        out.write_all(&encoded)?;
+
+        Ok(())
+    }
+}
+
+pub struct Qoi;
+
+impl Encoder for Qoi {
+    fn encode(&self, image: &Image, mut out: &mut dyn Write) -> Result<(), EncodingError> {
+        qoi::Encoder::new(&image.data, image.width, image.height)?.encode_to_stream(&mut out)?;
+
+        Ok(())
+    }
+}
+
+// 24-bit uncompressed BMP
+pub struct Bmp;
+
+impl Encoder for Bmp {
+    fn encode(&self, image: &Image, out: &mut dyn Write) -> Result<(), EncodingError> {
+        let max = i32::MAX as u32 / 4;
+
+        if image.width > max || image.height > max {
+            return Err(EncodingError::TooLarge { max });
+        }
+
+        let (w, h) = (image.width as usize, image.height as usize);
+
+        // rows are aligned on 4 bytes
+        let stride = (w * 3 + 3) & !3;
+        let offset = 14 + 40;
+        let size = offset + stride * h;
+
+        // BITMAPFILEHEADER
+        out.write_all(b"BM")?;
+        out.write_all(&(size as u32).to_le_bytes())?;
+        out.write_all(&[0; 4])?;
+        out.write_all(&(offset as u32).to_le_bytes())?;
+
+        // BITMAPINFOHEADER
+        out.write_all(&40u32.to_le_bytes())?;
+        out.write_all(&(w as i32).to_le_bytes())?;
+        out.write_all(&(h as i32).to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&24u16.to_le_bytes())?;
+        out.write_all(&[0; 24])?;
+
+        // BGR pixels, bottom row first
+        let mut row = vec![0u8; stride];
+
+        if w == 0 {
+            return Ok(());
+        }
+
+        for line in image.data.chunks_exact(w * 3).rev() {
+            for (src, dst) in line.chunks_exact(3).zip(row.chunks_exact_mut(3)) {
+                dst.copy_from_slice(&[src[2], src[1], src[0]]);
+            }
+
+            out.write_all(&row)?;
+        }
+
+        Ok(())
+    }
+}
+
+// binary PPM (P6)
+pub struct Ppm;
+
+impl Encoder for Ppm {
+    fn encode(&self, image: &Image, out: &mut dyn Write) -> Result<(), EncodingError> {
+        write!(out, "P6\n{} {}\n255\n", image.width, image.height)?;
+        out.write_all(&image.data)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // how far lossy formats may stray, on a channel
+    const LOSSY: u8 = 32;
+
+    fn gradient() -> Image {
+        let (width, height) = (7, 5);
+        let mut data = Vec::new();
+
+        for y in 0..height {
+            for x in 0..width {
+                data.extend_from_slice(&[x as u8 * 30, y as u8 * 50, 200 - x as u8 * 10]);
+            }
+        }
+
+        Image {
+            width,
+            height,
+            data,
+        }
+    }
+
+    fn encode(encoder: &dyn Encoder, image: &Image) -> Vec<u8> {
+        let mut out = Vec::new();
+        encoder.encode(image, &mut out).unwrap();
+
+        out
+    }
+
+    fn assert_close(decoded: &[u8], image: &Image, tolerance: u8) {
+        assert_eq!(decoded.len(), image.data.len());
+
+        for (i, (a, b)) in decoded.iter().zip(&image.data).enumerate() {
+            assert!(
+                a.abs_diff(*b) <= tolerance,
+                "byte {} is {}, expected {} (±{})",
+                i,
+                a,
+                b,
+                tolerance
+            );
+        }
+    }
+
+    #[test]
+    fn png_round_trips() {
+        let image = gradient();
+        let encoded = encode(&Png, &image);
+
+        let mut reader = png::Decoder::new(&encoded[..]).read_info().unwrap();
+        let mut decoded = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut decoded).unwrap();
+
+        assert_eq!((info.width, info.height), (7, 5));
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+        assert_eq!(decoded, image.data);
+    }
+
+    #[test]
+    fn jpeg_round_trips_closely() {
+        let image = gradient();
+        let encoded = encode(&Jpeg { quality: 95 }, &image);
+
+        let mut decoder = jpeg_decoder::Decoder::new(&encoded[..]);
+        let decoded = decoder.decode().unwrap();
+        let info = decoder.info().unwrap();
+
+        assert_eq!((info.width, info.height), (7, 5));
+        assert_eq!(info.pixel_format, jpeg_decoder::PixelFormat::RGB24);
+        assert_close(&decoded, &image, LOSSY);
+    }
+
+    #[test]
+    fn webp_round_trips() {
+        let image = gradient();
+
+        // lossless without a quality
+        let encoded = encode(&WebP { quality: None }, &image);
+        let decoded = webp::Decoder::new(&encoded).decode().unwrap();
+
+        assert_eq!((decoded.width(), decoded.height()), (7, 5));
+        assert!(!decoded.is_alpha());
+        assert_eq!(&*decoded, &image.data[..]);
+
+        let encoded = encode(&WebP { quality: Some(95) }, &image);
+        let decoded = webp::Decoder::new(&encoded).decode().unwrap();
+
+        assert_eq!((decoded.width(), decoded.height()), (7, 5));
+        assert_close(&decoded, &image, LOSSY);
+    }
+
+    #[test]
+    fn qoi_round_trips() {
+        let image = gradient();
+        let encoded = encode(&Qoi, &image);
+
+        let (header, decoded) = qoi::decode_to_vec(&encoded).unwrap();
+
+        assert_eq!((header.width, header.height), (7, 5));
+        assert_eq!(header.channels, qoi::Channels::Rgb);
+        assert_eq!(decoded, image.data);
+    }
+
+    // small enough to write out by hand, with an odd width so BMP rows get
+    // padded
+    fn tiny() -> Image {
+        Image {
+            width: 3,
+            height: 2,
+            data: (1..=18).collect(),
+        }
+    }
+
+    #[test]
+    fn bmp_matches_a_known_file() {
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            // BITMAPFILEHEADER: 78 bytes, pixels at 54
+            b'B', b'M', 78, 0, 0, 0, 0, 0, 0, 0, 54, 0, 0, 0,
+            // BITMAPINFOHEADER: 3x2, 1 plane, 24 bits, uncompressed
+            40, 0, 0, 0, 3, 0, 0, 0, 2, 0, 0, 0, 1, 0, 24, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            // BGR, bottom row first, rows padded to 4 bytes
+            12, 11, 10, 15, 14, 13, 18, 17, 16, 0, 0, 0,
+            3, 2, 1, 6, 5, 4, 9, 8, 7, 0, 0, 0,
+        ];
+
+        assert_eq!(encode(&Bmp, &tiny()), expected);
+    }
+
+    #[test]
+    fn ppm_matches_a_known_file() {
+        let mut expected = b"P6\n3 2\n255\n".to_vec();
+        expected.extend(1..=18);
+
+        assert_eq!(encode(&Ppm, &tiny()), expected);
+    }
+}
@@ -0,0 +1,67 @@
+use std::path::Path;
+
+mod encoder;
+
+pub use encoder::{Encoder, EncodingError};
+
+// a cropped screenshot, ready to be written out
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+
+    // RGB pixels, top row first
+    pub data: Vec<u8>,
+}
+
+// the formats images can be written in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    WebP,
+    Qoi,
+    Bmp,
+    Ppm,
+}
+
+impl Format {
+    pub const NAMES: &'static str = "png, jpeg, webp, qoi, bmp or ppm";
+
+    // guesses the format from a file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+
+    // quality goes from 1 to 100, lossless formats ignore it. webp is lossless
+    // unless a quality is given
+    pub fn encoder(self, quality: Option<u8>) -> Box<dyn Encoder> {
+        match self {
+            Format::Png => Box::new(encoder::Png),
+            Format::Jpeg => Box::new(encoder::Jpeg {
+                quality: quality.unwrap_or(90),
+            }),
+            Format::WebP => Box::new(encoder::WebP { quality }),
+            Format::Qoi => Box::new(encoder::Qoi),
+            Format::Bmp => Box::new(encoder::Bmp),
+            Format::Ppm => Box::new(encoder::Ppm),
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    // names and file extensions, case-insensitive
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "png" => Ok(Format::Png),
+            "jpeg" | "jpg" => Ok(Format::Jpeg),
+            "webp" => Ok(Format::WebP),
+            "qoi" => Ok(Format::Qoi),
+            "bmp" => Ok(Format::Bmp),
+            "ppm" => Ok(Format::Ppm),
+            _ => Err(format!("expected {}", Format::NAMES)),
+        }
+    }
+}
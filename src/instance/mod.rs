@@ -0,0 +1,12 @@
+use custom_error::custom_error;
+
+#[cfg_attr(windows, path = "windows.rs")]
+#[cfg_attr(unix, path = "unix.rs")]
+mod os;
+
+pub use os::lock;
+
+custom_error! { pub InstanceError
+    Io{source: std::io::Error} = "cannot check for another screensnap: {source}",
+    Running = "screensnap is already running",
+}
@@ -0,0 +1,52 @@
+use super::InstanceError;
+
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io,
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::PathBuf,
+};
+
+// released when dropped, or when the process dies
+pub struct InstanceLock(File);
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        unsafe {
+            libc::flock(self.0.as_raw_fd(), libc::LOCK_UN);
+        }
+    }
+}
+
+// the file stays, removing it would let two daemons lock different files
+fn lock_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("screensnap.lock"),
+        None => env::temp_dir().join(format!("screensnap-{}.lock", unsafe { libc::getuid() })),
+    }
+}
+
+// only one daemon per user grabs the hotkeys
+pub fn lock() -> Result<InstanceLock, InstanceError> {
+    // without XDG_RUNTIME_DIR the path is predictable in /tmp: don't follow
+    // links planted there, and don't truncate whatever it is
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .custom_flags(libc::O_NOFOLLOW)
+        .mode(0o600)
+        .open(lock_path())?;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let e = io::Error::last_os_error();
+
+        return match e.kind() {
+            io::ErrorKind::WouldBlock => Err(InstanceError::Running),
+            _ => Err(e.into()),
+        };
+    }
+
+    Ok(InstanceLock(file))
+}
@@ -0,0 +1,46 @@
+use super::InstanceError;
+
+use std::{ffi::OsStr, io, iter::once, os::windows::ffi::OsStrExt, ptr::null_mut};
+
+use winapi::{
+    shared::winerror::ERROR_ALREADY_EXISTS,
+    um::{
+        errhandlingapi::GetLastError, handleapi::CloseHandle, synchapi::CreateMutexW, winnt::HANDLE,
+    },
+};
+
+// released when dropped, or when the process dies
+pub struct InstanceLock(HANDLE);
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.0);
+        }
+    }
+}
+
+// only one daemon per session registers the hotkeys
+pub fn lock() -> Result<InstanceLock, InstanceError> {
+    let name: Vec<u16> = OsStr::new("Local\\screensnap")
+        .encode_wide()
+        .chain(once(0))
+        .collect();
+
+    let mutex = unsafe { CreateMutexW(null_mut(), 0, name.as_ptr()) };
+
+    if mutex.is_null() {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    // the mutex exists, we just got another handle to it
+    if unsafe { GetLastError() } == ERROR_ALREADY_EXISTS {
+        unsafe {
+            CloseHandle(mutex);
+        }
+
+        return Err(InstanceError::Running);
+    }
+
+    Ok(InstanceLock(mutex))
+}
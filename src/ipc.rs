@@ -0,0 +1,243 @@
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::{DirBuilderExt, MetadataExt},
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+// where the daemon listens: one request per connection, one line each way
+pub fn socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("screensnap.sock"),
+        // the runtime directory is per user, the temporary one isn't: we make
+        // a directory of our own there
+        None => env::temp_dir()
+            .join(format!("screensnap-{}", uid()))
+            .join("screensnap.sock"),
+    }
+}
+
+fn uid() -> libc::uid_t {
+    unsafe { libc::getuid() }
+}
+
+// makes sure only we can get into the socket's directory, so nobody else can
+// reach our socket or put theirs in its place (it may already be there, made
+// by us or by someone who got there first)
+fn private_dir(dir: &Path) -> io::Result<()> {
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+        _ => (),
+    }
+
+    let metadata = fs::symlink_metadata(dir)?;
+
+    if !metadata.is_dir() || metadata.uid() != uid() || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} isn't a directory only we can access", dir.display()),
+        ));
+    }
+
+    Ok(())
+}
+
+// who's listening on the other end
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let mut credentials: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(credentials.uid)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let (mut uid, mut gid) = (0, 0);
+
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(uid)
+}
+
+// removes the socket when the daemon stops
+pub struct Listener {
+    path: PathBuf,
+    // clients still being answered
+    answering: Arc<AtomicUsize>,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+
+        // let them get their reply (to "quit", mostly) before we exit
+        let start = Instant::now();
+
+        while self.answering.load(Ordering::SeqCst) > 0 && start.elapsed() < Duration::from_secs(1)
+        {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+// answers every request with the handler, each client on its own thread
+// (handlers may wait for the user to crop)
+pub fn listen<F>(handler: F) -> io::Result<Listener>
+where
+    F: Fn(&str) -> String + Send + Sync + 'static,
+{
+    listen_at(socket_path(), handler)
+}
+
+fn listen_at<F>(path: PathBuf, handler: F) -> io::Result<Listener>
+where
+    F: Fn(&str) -> String + Send + Sync + 'static,
+{
+    if let Some(dir) = path.parent() {
+        private_dir(dir)?;
+    }
+
+    // a daemon that crashed leaves its socket behind
+    if path.exists() && UnixStream::connect(&path).is_err() {
+        fs::remove_file(&path)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    let handler = Arc::new(handler);
+    let answering = Arc::new(AtomicUsize::new(0));
+
+    {
+        let answering = answering.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let handler = handler.clone();
+                let answering = answering.clone();
+
+                answering.fetch_add(1, Ordering::SeqCst);
+
+                thread::spawn(move || {
+                    if let Err(e) = answer(stream, &*handler) {
+                        if cfg!(debug_assertions) {
+                            eprintln!("cannot answer a client: {}", e);
+                        }
+                    }
+
+                    answering.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+    }
+
+    Ok(Listener { path, answering })
+}
+
+fn answer(stream: UnixStream, handler: &dyn Fn(&str) -> String) -> io::Result<()> {
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+
+    let mut reply = handler(request.trim());
+    reply.push('\n');
+
+    (&stream).write_all(reply.as_bytes())
+}
+
+// sends a request to the daemon and waits for its reply
+pub fn send(request: &str) -> io::Result<String> {
+    send_to(&socket_path(), request)
+}
+
+fn send_to(path: &Path, request: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(path)?;
+
+    // don't tell another user's daemon what to do, or believe its replies
+    if peer_uid(&stream)? != uid() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "the socket belongs to another user",
+        ));
+    }
+
+    writeln!(stream, "{}", request)?;
+
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply)?;
+
+    Ok(reply.trim_end().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::PermissionsExt;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("screensnap-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn answers_requests_on_the_socket() {
+        let dir = temp_dir("ipc");
+        let path = dir.join("screensnap.sock");
+
+        let listener = listen_at(path.clone(), |line| format!("got {}", line)).unwrap();
+        let mode = fs::metadata(&dir).unwrap().mode() & 0o777;
+        let replies = [
+            send_to(&path, "capture region").unwrap(),
+            send_to(&path, " quit ").unwrap(),
+        ];
+
+        drop(listener);
+        let left = path.exists();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(mode, 0o700);
+        assert_eq!(replies, ["got capture region", "got quit"]);
+        assert!(!left);
+    }
+
+    #[test]
+    fn refuses_a_directory_others_can_get_into() {
+        let dir = temp_dir("ipc-shared");
+        fs::create_dir(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+
+        let result = listen_at(dir.join("screensnap.sock"), |line| line.to_owned());
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(
+            result.err().map(|e| e.kind()),
+            Some(io::ErrorKind::PermissionDenied)
+        );
+    }
+}
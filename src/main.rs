@@ -1,43 +1,458 @@
-#![windows_subsystem = "windows"]
-
-use custom_error::custom_error;
-
-mod cropper;
-mod focuser;
-mod hotkey;
-mod msgbox;
-mod screengrab;
-
-use cropper::Cropper;
-use screengrab::Screenshot;
-
-custom_error! { ScreenshotError
-    Cropping{source: cropper::CropperError} = "error while cropping: {source:?}",
-}
-
-fn main() -> Result<(), ScreenshotError> {
-    // set the process to be DPI unaware
-    if cfg!(windows) {
-        unsafe {
-            winapi::um::shellscalingapi::SetProcessDpiAwareness(
-                winapi::um::shellscalingapi::PROCESS_DPI_UNAWARE,
-            );
-        }
-    }
-
-    // create the cropper
-    let mut cropper = Cropper::new()?;
-
-    hotkey::register(true, || {
-        // get screenshot
-        match cropper.apply(Screenshot::take()) {
-            Err(e) => {
-                msgbox::error(&format!("{:?}", e));
-                true
-            }
-            Ok(should_quit) => should_quit,
-        }
-    });
-
-    Ok(())
-}
+#![windows_subsystem = "windows"]
+
+use chrono::Local;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use custom_error::custom_error;
+use regex::Regex;
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    process::ExitCode,
+    time::Duration,
+};
+
+mod config;
+mod cropper;
+mod daemon;
+mod focuser;
+mod hotkey;
+mod image;
+mod instance;
+#[cfg(unix)]
+mod ipc;
+mod msgbox;
+#[cfg(all(test, unix))]
+mod private_bus;
+mod screengrab;
+mod template;
+
+use config::ConfigError;
+use cropper::{Cropper, Selection};
+use daemon::Action;
+use hotkey::{Binding, Chord};
+use image::{EncodingError, Format};
+use instance::InstanceError;
+use screengrab::{CaptureBackend, CaptureError, CaptureMode, ModeError, Rectangle, Screenshot};
+use template::Template;
+
+custom_error! { ScreenshotError
+    Cropping{source: cropper::CropperError} = "error while cropping: {source:?}",
+    Capture{source: CaptureError} = "cannot take a screenshot: {source}",
+    UnknownBackend{name: String} = "unknown capture backend: {name}",
+    Mode{source: ModeError} = "nothing to capture: {source}",
+    Output{source: io::Error} = "cannot write the output: {source}",
+    Encoding{source: EncodingError} = "cannot encode the image: {source}",
+    Hotkey{source: hotkey::HotkeyError} = "{source}",
+    Instance{source: InstanceError} = "{source}",
+    Config{source: ConfigError} = "{source}",
+    NothingToRepeat = "nothing to repeat yet",
+    Unreachable{reason: String} = "cannot reach the daemon: {reason}",
+    Daemon{reason: String} = "{reason}",
+}
+
+// exit codes, `capture` tells a cancelled crop apart from an error
+const EXIT_CANCELLED: u8 = 1;
+const EXIT_ERROR: u8 = 2;
+
+#[derive(Parser)]
+#[command(version, about = "Crop screenshots to a region or a window")]
+struct Cli {
+    /// How screenshots are taken: gdi, x11, wlr, portal or file:PATH
+    #[arg(long, global = true, value_name = "NAME")]
+    backend: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Wait for hotkeys (the default)
+    Daemon(DaemonArgs),
+    /// Take a single screenshot and exit
+    Capture(CaptureArgs),
+    /// Ask the running daemon to capture, like from a compositor keybinding
+    #[cfg(unix)]
+    Trigger(TriggerArgs),
+    /// Serve the clipboard after screensnap exits
+    #[cfg(unix)]
+    #[command(hide = true)]
+    ServeClipboard {
+        // the file the image was saved to, for text/uri-list
+        saved: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+struct DaemonArgs {
+    /// Where Ctrl+S saves, see `capture --help` [default: from the config,
+    /// or ~/Pictures/screensnap/%Y-%m-%d_%H-%M-%S.png]
+    #[arg(short, long, value_name = "TEMPLATE")]
+    output: Option<String>,
+
+    /// Bind a key chord (like Super+Shift+S) to crop, crop-to-file,
+    /// active-window, screen or current-monitor. Can be repeated, and replaces
+    /// the config's [hotkeys] or the defaults: Print=crop,
+    /// Ctrl+Print=active-window, Shift+Print=screen
+    #[arg(long = "bind", value_name = "CHORD=ACTION", value_parser = parse_binding)]
+    bindings: Vec<Binding<Action>>,
+}
+
+#[cfg(unix)]
+#[derive(Args)]
+struct TriggerArgs {
+    /// An action (region, region-to-file, window, screen, monitor),
+    /// repeat-last or quit
+    #[arg(value_name = "REQUEST", value_parser = parse_request)]
+    request: String,
+}
+
+#[derive(Args)]
+struct CaptureArgs {
+    #[command(flatten)]
+    target: Target,
+
+    /// Write the image to a file, or to stdout with "-". The name can contain
+    /// strftime codes (%Y-%m-%d...), {window}, {monitor}, {width}, {height}
+    /// and {counter} (or {counter:N} for N digits)
+    #[arg(short, long, value_name = "TEMPLATE")]
+    output: Option<String>,
+
+    /// Image format of the output: png, jpeg, webp, qoi, bmp or ppm
+    /// [default: from the file extension, or png]
+    #[arg(long, value_name = "FORMAT")]
+    format: Option<Format>,
+
+    /// Quality of lossy formats, from 1 to 100 (makes webp lossy)
+    #[arg(long, value_name = "Q", value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+
+    /// Copy to the clipboard (the default without --output)
+    #[arg(long)]
+    clipboard: bool,
+
+    /// Wait before taking the screenshot
+    #[arg(long, value_name = "SECONDS", value_parser = parse_delay)]
+    delay: Option<Duration>,
+}
+
+// what to capture, the whole virtual screen by default
+#[derive(Args)]
+#[group(multiple = false)]
+struct Target {
+    /// A region of the virtual screen
+    #[arg(long, value_name = "X,Y,W,H", value_parser = parse_region)]
+    region: Option<Rectangle<i32>>,
+
+    /// The topmost window whose title matches a regular expression
+    #[arg(long, value_name = "TITLE")]
+    window: Option<Regex>,
+
+    /// The focused window
+    #[arg(long)]
+    active: bool,
+
+    /// A monitor, counting from 0
+    #[arg(long, value_name = "N")]
+    monitor: Option<usize>,
+
+    /// The monitor under the mouse
+    #[arg(long)]
+    current_monitor: bool,
+
+    /// Let the user crop the screenshot
+    #[arg(short, long)]
+    interactive: bool,
+}
+
+impl Target {
+    fn mode(&self) -> CaptureMode {
+        if let Some(region) = self.region {
+            CaptureMode::Region(region)
+        } else if let Some(pattern) = &self.window {
+            CaptureMode::WindowTitle(pattern.clone())
+        } else if self.active {
+            CaptureMode::ActiveWindow
+        } else if let Some(index) = self.monitor {
+            CaptureMode::Monitor(index)
+        } else if self.current_monitor {
+            CaptureMode::CurrentMonitor
+        } else {
+            CaptureMode::Screen
+        }
+    }
+}
+
+fn parse_region(region: &str) -> Result<Rectangle<i32>, String> {
+    let values = region
+        .split(',')
+        .map(|v| v.trim().parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    match values[..] {
+        [x, y, w, h] if w > 0 && h > 0 => Ok(Rectangle { x, y, w, h }),
+        [_, _, _, _] => Err(String::from("the width and height must be positive")),
+        _ => Err(String::from("expected X,Y,W,H")),
+    }
+}
+
+fn parse_binding(binding: &str) -> Result<Binding<Action>, String> {
+    let (chord, action) = binding
+        .split_once('=')
+        .ok_or_else(|| String::from("expected CHORD=ACTION"))?;
+
+    Ok(Binding {
+        chord: chord.parse::<Chord>()?,
+        action: Action::from_str(action.trim(), true)?,
+    })
+}
+
+// checked here, but sent as it is
+#[cfg(unix)]
+fn parse_request(request: &str) -> Result<String, String> {
+    request.parse::<daemon::Request>()?;
+    Ok(request.to_owned())
+}
+
+fn parse_delay(delay: &str) -> Result<Duration, String> {
+    match delay.parse::<f64>() {
+        Ok(secs) if secs.is_finite() && secs >= 0.0 => Ok(Duration::from_secs_f64(secs)),
+        Ok(_) => Err(String::from("must be a positive number of seconds")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn take(backends: &[Box<dyn CaptureBackend>]) -> Result<Screenshot, ScreenshotError> {
+    match Screenshot::take_with(backends) {
+        Ok(snap) => Ok(snap),
+
+        // some windows won't be snapped to, but the screenshot is fine
+        Err(CaptureError::PartialWindows { failed, snap }) => {
+            eprintln!("{} windows couldn't be listed", failed);
+            Ok(*snap)
+        }
+
+        Err(e) => Err(e.into()),
+    }
+}
+
+// writes a region to a file named after a template, or to stdout with "-".
+// without a format, it's guessed from the extension (PNG by default). returns
+// the path of the file
+fn save(
+    snap: &Screenshot,
+    region: Rectangle<u32>,
+    output: &str,
+    format: Option<Format>,
+    quality: Option<u8>,
+) -> Result<Option<PathBuf>, ScreenshotError> {
+    let image = snap.crop(region);
+
+    if output == "-" {
+        let mut out = io::stdout().lock();
+
+        let encoder = format.unwrap_or(Format::Png).encoder(quality);
+        encoder.encode(&image, &mut out)?;
+
+        out.flush()?;
+        return Ok(None);
+    }
+
+    let (path, file) = Template::new(output).create(&template::Context {
+        time: Local::now(),
+        window: snap.window_under(region).map(|w| w.title.as_str()),
+        monitor: snap.monitor_under(region).map(|m| m.name.as_str()),
+        width: image.width,
+        height: image.height,
+    })?;
+
+    let mut out = BufWriter::new(file);
+
+    let encoder = format
+        .or_else(|| Format::from_path(&path))
+        .unwrap_or(Format::Png)
+        .encoder(quality);
+    let written = encoder
+        .encode(&image, &mut out)
+        .map_err(ScreenshotError::from)
+        .and_then(|()| out.flush().map_err(ScreenshotError::from));
+
+    // the file was created for it, don't leave it half written
+    if let Err(e) = written {
+        drop(out);
+        let _ = fs::remove_file(&path);
+
+        return Err(e);
+    }
+
+    Ok(Some(path))
+}
+
+// the hotkey loop
+fn daemon(
+    args: &DaemonArgs,
+    backends: &[Box<dyn CaptureBackend>],
+) -> Result<bool, ScreenshotError> {
+    // held until the daemon quits (Ctrl-Shift-Q releases it too)
+    let _lock = match instance::lock() {
+        Ok(lock) => lock,
+
+        // launching it again crops with the running daemon, when it can be
+        // reached
+        #[cfg(unix)]
+        Err(InstanceError::Running) => {
+            return trigger("capture region").map_err(|e| match e {
+                ScreenshotError::Unreachable { .. } => InstanceError::Running.into(),
+                e => e,
+            })
+        }
+
+        Err(e) => return Err(e.into()),
+    };
+
+    let result = daemon::run(args, config::load()?, backends);
+
+    // the last capture it copied stays in the clipboard
+    screengrab::persist_clipboard();
+
+    result?;
+    Ok(true)
+}
+
+// asks the running daemon, returns false when the user cancelled
+#[cfg(unix)]
+fn trigger(request: &str) -> Result<bool, ScreenshotError> {
+    let reply = ipc::send(request).map_err(|e| ScreenshotError::Unreachable {
+        reason: e.to_string(),
+    })?;
+
+    match reply.as_str() {
+        daemon::REPLY_OK => Ok(true),
+        daemon::REPLY_CANCELLED => Ok(false),
+        "" => Err(ScreenshotError::Unreachable {
+            reason: String::from("it didn't answer"),
+        }),
+        reply => Err(ScreenshotError::Daemon {
+            reason: reply
+                .strip_prefix(daemon::REPLY_ERROR)
+                .unwrap_or(reply)
+                .to_owned(),
+        }),
+    }
+}
+
+// returns false when the user cancelled
+fn capture(
+    args: &CaptureArgs,
+    backends: &[Box<dyn CaptureBackend>],
+) -> Result<bool, ScreenshotError> {
+    // only the cropper and Ctrl+S use the config
+    let config = match args.target.interactive {
+        true => config::load()?,
+        false => config::Config::default(),
+    };
+
+    // create the cropper before the delay, it takes a moment
+    let mut cropper = match args.target.interactive {
+        true => Some(Cropper::new()?),
+        false => None,
+    };
+
+    if let Some(cropper) = &mut cropper {
+        cropper.set_style(config.style);
+    }
+
+    if let Some(delay) = args.delay {
+        std::thread::sleep(delay);
+    }
+
+    let snap = take(backends)?;
+
+    let region = match &mut cropper {
+        Some(cropper) => match cropper.apply(&snap)? {
+            Selection::Region(region) => region,
+
+            // Ctrl+S saves even without --output, where the config says
+            Selection::Save(region) => {
+                let (output, format) = match (&args.output, &config.output) {
+                    (Some(output), _) => (output.as_str(), args.format),
+                    (None, Some(output)) => (output.as_str(), args.format.or(config.format)),
+                    (None, None) => (template::DEFAULT, args.format.or(config.format)),
+                };
+
+                let saved = save(&snap, region, output, format, args.quality)?;
+
+                if args.clipboard {
+                    snap.copy_to_clipboard(region, saved.as_deref());
+                    screengrab::persist_clipboard();
+                }
+
+                return Ok(true);
+            }
+
+            Selection::Cancelled | Selection::Quit => return Ok(false),
+        },
+        None => args.target.mode().region(&snap)?,
+    };
+
+    let saved = match &args.output {
+        Some(output) => save(&snap, region, output, args.format, args.quality)?,
+        None => None,
+    };
+
+    if args.clipboard || args.output.is_none() {
+        snap.copy_to_clipboard(region, saved.as_deref());
+
+        // on linux, the clipboard is lost when we exit
+        screengrab::persist_clipboard();
+    }
+
+    Ok(true)
+}
+
+fn run(cli: Cli) -> Result<bool, ScreenshotError> {
+    let backends = screengrab::backends(cli.backend.as_deref()).ok_or_else(|| {
+        ScreenshotError::UnknownBackend {
+            name: cli.backend.clone().unwrap_or_default(),
+        }
+    })?;
+
+    match cli.command.unwrap_or(Command::Daemon(DaemonArgs {
+        output: None,
+        bindings: Vec::new(),
+    })) {
+        Command::Daemon(args) => daemon(&args, &backends),
+        Command::Capture(args) => capture(&args, &backends),
+        #[cfg(unix)]
+        Command::Trigger(args) => trigger(&args.request),
+        #[cfg(unix)]
+        Command::ServeClipboard { saved } => Ok(screengrab::serve_clipboard(saved)),
+    }
+}
+
+fn main() -> ExitCode {
+    // set the process to be DPI unaware
+    #[cfg(windows)]
+    unsafe {
+        winapi::um::shellscalingapi::SetProcessDpiAwareness(
+            winapi::um::shellscalingapi::PROCESS_DPI_UNAWARE,
+        );
+    }
+
+    // same on X11: winit would scale the cropper by the screen's DPI, and its
+    // logical coordinates wouldn't match the screenshot's pixels anymore
+    // (only X11 reads it, wayland screenshots are logical already)
+    #[cfg(unix)]
+    std::env::set_var("WINIT_HIDPI_FACTOR", "1");
+
+    match run(Cli::parse()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(EXIT_CANCELLED),
+        Err(e) => {
+            msgbox::error(&e.to_string());
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
@@ -1,4 +1,5 @@
 #[cfg_attr(windows, path = "windows.rs")]
+#[cfg_attr(unix, path = "unix.rs")]
 mod os;
 
 pub use os::error;
@@ -0,0 +1,4 @@
+pub fn error(msg: &str) {
+    // there's no system message box outside of windows
+    eprintln!("error: {}", msg);
+}
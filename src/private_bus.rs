@@ -0,0 +1,73 @@
+// a d-bus daemon for the tests, so they don't touch the session bus
+
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::Path,
+    process::{Child, Command, Stdio},
+};
+use zbus::blocking::{connection, Connection};
+
+// a bus of our own, killed when dropped
+pub struct PrivateBus {
+    daemon: Child,
+    address: String,
+}
+
+impl PrivateBus {
+    pub fn start(dir: &Path) -> Self {
+        let config = dir.join("bus.conf");
+
+        fs::write(
+            &config,
+            format!(
+                "<busconfig>\
+                 <type>session</type>\
+                 <listen>unix:path={}</listen>\
+                 <policy context=\"default\">\
+                 <allow send_destination=\"*\"/>\
+                 <allow receive_sender=\"*\"/>\
+                 <allow own=\"*\"/>\
+                 </policy>\
+                 </busconfig>",
+                dir.join("bus").display()
+            ),
+        )
+        .unwrap();
+
+        let mut daemon = Command::new("dbus-daemon")
+            .arg(format!("--config-file={}", config.display()))
+            .args(["--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("cannot start dbus-daemon");
+
+        // it prints its address once it listens
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+
+        PrivateBus {
+            daemon,
+            address: address.trim().to_owned(),
+        }
+    }
+
+    // to serve something on the bus
+    pub fn builder(&self) -> connection::Builder<'_> {
+        connection::Builder::address(self.address.as_str()).unwrap()
+    }
+
+    pub fn connect(&self) -> Connection {
+        self.builder().build().unwrap()
+    }
+}
+
+impl Drop for PrivateBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
@@ -0,0 +1,214 @@
+use crate::image::{Format, Image};
+
+use custom_error::custom_error;
+use std::{
+    env,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::unix::process::CommandExt,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+mod wayland;
+mod x11;
+
+custom_error! { pub ClipboardError
+    Unavailable = "no display to copy to",
+    Protocol{reason: String} = "{reason}",
+    NotOwner = "another client kept the clipboard",
+    Io{source: io::Error} = "{source}",
+}
+
+impl ClipboardError {
+    pub fn protocol<E: std::fmt::Display>(e: E) -> Self {
+        ClipboardError::Protocol {
+            reason: e.to_string(),
+        }
+    }
+}
+
+// the hidden subcommand that keeps the clipboard once we exit
+const SERVER_COMMAND: &str = "serve-clipboard";
+
+// the last copied image
+struct Copied {
+    image: Image,
+    // where it was saved, for text/uri-list
+    saved: Option<PathBuf>,
+    // whether we still own the clipboard
+    owned: Arc<AtomicBool>,
+}
+
+static COPIED: Mutex<Option<Copied>> = Mutex::new(None);
+
+// takes the clipboard, and keeps serving the image from a background thread
+// until another client copies something
+pub fn copy(image: Image, saved: Option<PathBuf>) -> Result<(), ClipboardError> {
+    let owned = Arc::new(AtomicBool::new(true));
+    start(image.clone(), saved.clone(), owned.clone())?;
+
+    *COPIED.lock().unwrap() = Some(Copied {
+        image,
+        saved,
+        owned,
+    });
+
+    Ok(())
+}
+
+fn start(
+    image: Image,
+    saved: Option<PathBuf>,
+    owned: Arc<AtomicBool>,
+) -> Result<JoinHandle<()>, ClipboardError> {
+    // the same call path for both, wayland sessions may have XWayland too,
+    // and compositors without wlr-data-control only take it that way
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        match wayland::copy(image.clone(), owned.clone()) {
+            Err(ClipboardError::Unavailable) => (),
+            result => return result,
+        }
+    }
+
+    x11::copy(image, saved, owned)
+}
+
+// the clipboard dies with its owner: hand the image over to a background
+// process that serves it until another client copies something
+pub fn persist() -> Result<(), ClipboardError> {
+    let Copied { image, saved, .. } = match COPIED.lock().unwrap().take() {
+        Some(copied) if copied.owned.load(Ordering::Relaxed) => copied,
+        _ => return Ok(()),
+    };
+
+    // a session of its own, so that closing the terminal (SIGHUP) or Ctrl-C
+    // (SIGINT to the foreground group) doesn't take the clipboard with it
+    let mut command = Command::new(env::current_exe()?);
+    command
+        .arg(SERVER_COMMAND)
+        .args(saved)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+
+    unsafe {
+        command.pre_exec(|| match libc::setsid() {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        });
+    }
+
+    let mut server = command.spawn()?;
+
+    let mut stdin = server.stdin.take().unwrap();
+    let written = Format::Ppm.encoder(None).encode(&image, &mut stdin);
+    drop(stdin);
+
+    if let Err(e) = written {
+        return Err(ClipboardError::protocol(e));
+    }
+
+    // the server says when it owns the clipboard, and exits if it can't
+    let mut ready = [0];
+
+    match server.stdout.take().unwrap().read(&mut ready)? {
+        0 => Err(ClipboardError::NotOwner),
+        _ => Ok(()),
+    }
+}
+
+// the background process: reads a PPM image from stdin, and serves it
+// until it loses the clipboard
+pub fn serve(saved: Option<PathBuf>) -> Result<(), ClipboardError> {
+    let image = read_ppm(&mut BufReader::new(io::stdin().lock()))?;
+    let server = start(image, saved, Arc::new(AtomicBool::new(true)))?;
+
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(b"\n")?;
+    stdout.flush()?;
+
+    let _ = server.join();
+
+    Ok(())
+}
+
+// only what the PPM encoder writes
+fn read_ppm(input: &mut impl BufRead) -> Result<Image, ClipboardError> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a PPM image");
+
+    let mut header = Vec::new();
+
+    while header.len() < 4 {
+        let mut line = String::new();
+        input.read_line(&mut line)?;
+
+        if line.is_empty() {
+            return Err(invalid().into());
+        }
+
+        header.extend(line.split_whitespace().map(str::to_owned));
+    }
+
+    let (width, height) = match &header[..] {
+        [magic, width, height, depth] if magic == "P6" && depth == "255" => (
+            width.parse::<u32>().map_err(|_| invalid())?,
+            height.parse::<u32>().map_err(|_| invalid())?,
+        ),
+        _ => return Err(invalid().into()),
+    };
+
+    let mut data = vec![0; width as usize * height as usize * 3];
+    input.read_exact(&mut data)?;
+
+    Ok(Image {
+        width,
+        height,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_what_the_ppm_encoder_writes() {
+        let image = Image {
+            width: 3,
+            height: 2,
+            data: (0..18).collect(),
+        };
+
+        let mut ppm = Vec::new();
+        Format::Ppm.encoder(None).encode(&image, &mut ppm).unwrap();
+        // and nothing more is read from the pipe
+        ppm.extend_from_slice(b"next");
+
+        let mut input = ppm.as_slice();
+        let read = read_ppm(&mut input).unwrap();
+
+        assert_eq!((read.width, read.height), (3, 2));
+        assert_eq!(read.data, image.data);
+        assert_eq!(input, b"next");
+    }
+
+    #[test]
+    fn rejects_other_images() {
+        for bad in [
+            &b""[..],
+            b"P5\n3 2\n255\n",
+            b"P6\n3 2\n65535\n",
+            b"P6\n3 two\n255\n",
+            b"P6\n3\n",
+            // too short
+            b"P6\n3 2\n255\n\x00\x01",
+        ] {
+            assert!(read_ppm(&mut &bad[..]).is_err(), "{:?}", bad);
+        }
+    }
+}
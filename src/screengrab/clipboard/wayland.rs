@@ -0,0 +1,169 @@
+use super::ClipboardError;
+use crate::image::{Format, Image};
+
+use std::{
+    fs::File,
+    io::Write,
+    os::unix::io::OwnedFd,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+use wayland_client::{
+    delegate_noop, event_created_child,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{wl_registry::WlRegistry, wl_seat::WlSeat},
+    Connection, Dispatch, Proxy, QueueHandle,
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+    zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+};
+
+const MIME_TYPE: &str = "image/png";
+
+struct State {
+    image: Image,
+    // encoded on the first paste
+    png: Option<Vec<u8>>,
+    cancelled: bool,
+}
+
+impl State {
+    fn send(&mut self, mime_type: &str, fd: OwnedFd) {
+        if mime_type != MIME_TYPE {
+            return;
+        }
+
+        let image = &self.image;
+        let png = self.png.get_or_insert_with(|| {
+            let mut data = Vec::new();
+
+            if let Err(e) = Format::Png.encoder(None).encode(image, &mut data) {
+                eprintln!("cannot encode the clipboard image: {}", e);
+            }
+
+            data
+        });
+
+        // the reader may have given up already
+        if let Err(e) = File::from(fd).write_all(png) {
+            if cfg!(debug_assertions) {
+                eprintln!("cannot send the clipboard: {}", e);
+            }
+        }
+    }
+}
+
+pub fn copy(image: Image, owned: Arc<AtomicBool>) -> Result<JoinHandle<()>, ClipboardError> {
+    let conn = Connection::connect_to_env().map_err(|_| ClipboardError::Unavailable)?;
+    let (globals, mut queue) =
+        registry_queue_init::<State>(&conn).map_err(ClipboardError::protocol)?;
+    let qh = queue.handle();
+
+    let seat: WlSeat = globals
+        .bind(&qh, 1..=1, ())
+        .map_err(ClipboardError::protocol)?;
+
+    // wl_data_device only takes the selection with the serial of an input
+    // event on a focused surface of the same client, or silently ignores it.
+    // the cropper's window is winit's, on another connection, and it's gone
+    // by now. wlr-data-control (sway, river, KDE...) has no such requirement,
+    // the others (GNOME, weston) go through XWayland instead, see the README
+    let manager: ZwlrDataControlManagerV1 = globals
+        .bind(&qh, 1..=2, ())
+        .map_err(|_| ClipboardError::Unavailable)?;
+    let device = manager.get_data_device(&seat, &qh, ());
+    let source = manager.create_data_source(&qh, ());
+
+    source.offer(MIME_TYPE.to_owned());
+    device.set_selection(Some(&source));
+
+    let mut state = State {
+        image,
+        png: None,
+        cancelled: false,
+    };
+
+    queue
+        .roundtrip(&mut state)
+        .map_err(ClipboardError::protocol)?;
+
+    if state.cancelled {
+        return Err(ClipboardError::NotOwner);
+    }
+
+    // serve pastes until another client copies something
+    Ok(thread::spawn(move || {
+        while !state.cancelled {
+            if let Err(e) = queue.blocking_dispatch(&mut state) {
+                eprintln!("cannot serve the clipboard: {}", e);
+                break;
+            }
+        }
+
+        owned.store(false, Ordering::Relaxed);
+    }))
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: <WlRegistry as Proxy>::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrDataControlSourceV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        source: &ZwlrDataControlSourceV1,
+        event: zwlr_data_control_source_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_data_control_source_v1::Event::Send { mime_type, fd } => {
+                state.send(&mime_type, fd)
+            }
+            zwlr_data_control_source_v1::Event::Cancelled => {
+                source.destroy();
+                state.cancelled = true;
+            }
+            _ => (),
+        }
+    }
+}
+
+// the device announces what other clients copy, we don't read it
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrDataControlDeviceV1,
+        event: zwlr_data_control_device_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_data_control_device_v1::Event::Finished = event {
+            state.cancelled = true;
+        }
+    }
+
+    event_created_child!(State, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ()),
+    ]);
+}
+
+delegate_noop!(State: ignore WlSeat);
+delegate_noop!(State: ZwlrDataControlManagerV1);
+delegate_noop!(State: ignore ZwlrDataControlOfferV1);
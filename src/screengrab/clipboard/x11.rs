@@ -0,0 +1,442 @@
+use super::ClipboardError;
+use crate::image::{Format, Image};
+
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+use x11rb::{
+    connection::{Connection, RequestConnection},
+    protocol::{
+        xproto::{
+            Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, CreateWindowAux,
+            EventMask, PropMode, Property, PropertyNotifyEvent, SelectionNotifyEvent,
+            SelectionRequestEvent, Timestamp, Window, WindowClass, SELECTION_NOTIFY_EVENT,
+        },
+        Event,
+    },
+    rust_connection::RustConnection,
+    wrapper::ConnectionExt as _,
+    COPY_DEPTH_FROM_PARENT, CURRENT_TIME, NONE,
+};
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        CLIPBOARD,
+        TARGETS,
+        TIMESTAMP,
+        INCR,
+        IMAGE_PNG: b"image/png",
+        IMAGE_BMP: b"image/bmp",
+        TEXT_URI_LIST: b"text/uri-list",
+    }
+}
+
+pub fn copy(
+    image: Image,
+    saved: Option<PathBuf>,
+    owned: Arc<AtomicBool>,
+) -> Result<JoinHandle<()>, ClipboardError> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(|_| ClipboardError::Unavailable)?;
+    let root = conn.setup().roots[screen_num].root;
+
+    let atoms = Atoms::new(&conn)
+        .map_err(ClipboardError::protocol)?
+        .reply()
+        .map_err(ClipboardError::protocol)?;
+
+    // an invisible window to own the selection
+    let window = conn.generate_id().map_err(ClipboardError::protocol)?;
+
+    conn.create_window(
+        COPY_DEPTH_FROM_PARENT,
+        window,
+        root,
+        0,
+        0,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_ONLY,
+        0,
+        &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+    )
+    .map_err(ClipboardError::protocol)?;
+
+    let time = server_time(&conn, window)?;
+
+    conn.set_selection_owner(window, atoms.CLIPBOARD, time)
+        .map_err(ClipboardError::protocol)?;
+
+    let owner = conn
+        .get_selection_owner(atoms.CLIPBOARD)
+        .map_err(ClipboardError::protocol)?
+        .reply()
+        .map_err(ClipboardError::protocol)?
+        .owner;
+
+    if owner != window {
+        return Err(ClipboardError::NotOwner);
+    }
+
+    // large properties go through INCR, in chunks well under the request limit
+    let chunk_size = conn.maximum_request_bytes() / 4;
+
+    Ok(thread::spawn(move || {
+        let mut server = Server {
+            conn,
+            atoms,
+            window,
+            time,
+            chunk_size,
+            image,
+            png: None,
+            bmp: None,
+            saved,
+            file: None,
+            transfers: Vec::new(),
+        };
+
+        if let Err(e) = server.run() {
+            eprintln!("cannot serve the clipboard: {}", e);
+        }
+
+        owned.store(false, Ordering::Relaxed);
+        server.cleanup();
+    }))
+}
+
+// ICCCM wants a real timestamp when taking a selection: get one from the
+// PropertyNotify of an empty property change
+fn server_time(conn: &RustConnection, window: Window) -> Result<Timestamp, ClipboardError> {
+    conn.change_property8(
+        PropMode::APPEND,
+        window,
+        AtomEnum::WM_NAME,
+        AtomEnum::STRING,
+        &[],
+    )
+    .map_err(ClipboardError::protocol)?;
+    conn.flush().map_err(ClipboardError::protocol)?;
+
+    loop {
+        match conn.wait_for_event().map_err(ClipboardError::protocol)? {
+            Event::PropertyNotify(e) if e.window == window => return Ok(e.time),
+            _ => (),
+        }
+    }
+}
+
+// an INCR transfer in progress
+struct Transfer {
+    requestor: Window,
+    property: Atom,
+    type_: Atom,
+    data: Rc<Vec<u8>>,
+    offset: usize,
+}
+
+struct Server {
+    conn: RustConnection,
+    atoms: Atoms,
+    window: Window,
+    time: Timestamp,
+    chunk_size: usize,
+
+    // encoded lazily, most pastes only want one format
+    image: Image,
+    png: Option<Rc<Vec<u8>>>,
+    bmp: Option<Rc<Vec<u8>>>,
+    // where Ctrl+S saved it, or a file of our own that goes with the clipboard
+    saved: Option<PathBuf>,
+    file: Option<PathBuf>,
+
+    transfers: Vec<Transfer>,
+}
+
+impl Server {
+    fn run(&mut self) -> Result<(), ClipboardError> {
+        let mut owner = true;
+
+        // after losing the selection, finish the transfers in progress
+        while owner || !self.transfers.is_empty() {
+            match self
+                .conn
+                .wait_for_event()
+                .map_err(ClipboardError::protocol)?
+            {
+                Event::SelectionClear(e) if e.selection == self.atoms.CLIPBOARD => owner = false,
+                Event::SelectionRequest(e) if owner => self.request(&e)?,
+                Event::PropertyNotify(e) if e.state == Property::DELETE => self.send_chunk(&e)?,
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn request(&mut self, e: &SelectionRequestEvent) -> Result<(), ClipboardError> {
+        // obsolete clients don't name a property
+        let property = match e.property {
+            NONE => e.target,
+            property => property,
+        };
+
+        // requests from before we owned it get nothing
+        let served = e.selection == self.atoms.CLIPBOARD
+            && (e.time == CURRENT_TIME || e.time >= self.time)
+            && self.serve(e.requestor, property, e.target)?;
+
+        let notify = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: e.time,
+            requestor: e.requestor,
+            selection: e.selection,
+            target: e.target,
+            property: if served { property } else { NONE },
+        };
+
+        self.conn
+            .send_event(false, e.requestor, EventMask::NO_EVENT, notify)
+            .map_err(ClipboardError::protocol)?;
+        self.conn.flush().map_err(ClipboardError::protocol)?;
+
+        Ok(())
+    }
+
+    // writes the target to the requestor's property, returns false for
+    // targets we don't have
+    fn serve(
+        &mut self,
+        requestor: Window,
+        property: Atom,
+        target: Atom,
+    ) -> Result<bool, ClipboardError> {
+        let atoms = self.atoms;
+
+        if target == atoms.TARGETS {
+            let mut targets = vec![
+                atoms.TARGETS,
+                atoms.TIMESTAMP,
+                atoms.IMAGE_PNG,
+                atoms.IMAGE_BMP,
+            ];
+
+            // don't offer a path we couldn't write the image to
+            if self.file().is_some() {
+                targets.push(atoms.TEXT_URI_LIST);
+            }
+
+            self.conn
+                .change_property32(
+                    PropMode::REPLACE,
+                    requestor,
+                    property,
+                    AtomEnum::ATOM,
+                    &targets,
+                )
+                .map_err(ClipboardError::protocol)?;
+
+            return Ok(true);
+        }
+
+        if target == atoms.TIMESTAMP {
+            self.conn
+                .change_property32(
+                    PropMode::REPLACE,
+                    requestor,
+                    property,
+                    AtomEnum::INTEGER,
+                    &[self.time],
+                )
+                .map_err(ClipboardError::protocol)?;
+
+            return Ok(true);
+        }
+
+        let data = match self.data(target) {
+            Some(data) => data,
+            None => return Ok(false),
+        };
+
+        if data.len() <= self.chunk_size {
+            self.conn
+                .change_property8(PropMode::REPLACE, requestor, property, target, &data)
+                .map_err(ClipboardError::protocol)?;
+
+            return Ok(true);
+        }
+
+        // too large for a single request: announce the size, then send a chunk
+        // every time the requestor deletes the property
+        self.conn
+            .change_window_attributes(
+                requestor,
+                &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+            )
+            .map_err(ClipboardError::protocol)?;
+        self.conn
+            .change_property32(
+                PropMode::REPLACE,
+                requestor,
+                property,
+                atoms.INCR,
+                &[data.len() as u32],
+            )
+            .map_err(ClipboardError::protocol)?;
+
+        self.transfers.push(Transfer {
+            requestor,
+            property,
+            type_: target,
+            data,
+            offset: 0,
+        });
+
+        Ok(true)
+    }
+
+    fn send_chunk(&mut self, e: &PropertyNotifyEvent) -> Result<(), ClipboardError> {
+        let i = match self
+            .transfers
+            .iter()
+            .position(|t| t.requestor == e.window && t.property == e.atom)
+        {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+
+        let transfer = &mut self.transfers[i];
+        let end = (transfer.offset + self.chunk_size).min(transfer.data.len());
+
+        // the last chunk is empty
+        self.conn
+            .change_property8(
+                PropMode::REPLACE,
+                transfer.requestor,
+                transfer.property,
+                transfer.type_,
+                &transfer.data[transfer.offset..end],
+            )
+            .map_err(ClipboardError::protocol)?;
+        self.conn.flush().map_err(ClipboardError::protocol)?;
+
+        if transfer.offset == end {
+            let transfer = self.transfers.remove(i);
+
+            // stop listening to the requestor
+            let _ = self.conn.change_window_attributes(
+                transfer.requestor,
+                &ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT),
+            );
+        } else {
+            transfer.offset = end;
+        }
+
+        Ok(())
+    }
+
+    fn data(&mut self, target: Atom) -> Option<Rc<Vec<u8>>> {
+        let atoms = self.atoms;
+
+        if target == atoms.IMAGE_PNG {
+            Some(encode(&mut self.png, &self.image, Format::Png))
+        } else if target == atoms.IMAGE_BMP {
+            Some(encode(&mut self.bmp, &self.image, Format::Bmp))
+        } else if target == atoms.TEXT_URI_LIST {
+            let path = self.file()?;
+            Some(Rc::new(format!("{}\r\n", file_uri(&path)).into_bytes()))
+        } else {
+            None
+        }
+    }
+
+    // the image as a file, for the apps that want a path
+    fn file(&mut self) -> Option<PathBuf> {
+        if let Some(saved) = &self.saved {
+            return Some(saved.clone());
+        }
+
+        if self.file.is_none() {
+            let png = encode(&mut self.png, &self.image, Format::Png);
+
+            // create_new doesn't follow links other users could plant in /tmp
+            let dir = env::var_os("XDG_RUNTIME_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(env::temp_dir);
+            let path = dir.join(format!(
+                "screensnap-{}-{}.png",
+                std::process::id(),
+                self.time
+            ));
+
+            let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => file,
+                Err(e) => {
+                    eprintln!("cannot create {}: {}", path.display(), e);
+                    return None;
+                }
+            };
+
+            // don't leave half a file around
+            if let Err(e) = file.write_all(&png) {
+                eprintln!("cannot write {}: {}", path.display(), e);
+                let _ = fs::remove_file(&path);
+                return None;
+            }
+
+            self.file = Some(path);
+        }
+
+        self.file.clone()
+    }
+
+    fn cleanup(&self) {
+        let _ = self.conn.destroy_window(self.window);
+        let _ = self.conn.flush();
+
+        // our own file goes with the clipboard, the saved one stays
+        if let Some(file) = &self.file {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+fn encode(cache: &mut Option<Rc<Vec<u8>>>, image: &Image, format: Format) -> Rc<Vec<u8>> {
+    cache
+        .get_or_insert_with(|| {
+            let mut data = Vec::new();
+
+            if let Err(e) = format.encoder(None).encode(image, &mut data) {
+                eprintln!("cannot encode the clipboard image: {}", e);
+            }
+
+            Rc::new(data)
+        })
+        .clone()
+}
+
+// file:// URI, percent-encoding everything but unreserved characters
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+
+    for &byte in path.to_string_lossy().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            byte => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    uri
+}
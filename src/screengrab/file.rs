@@ -0,0 +1,71 @@
+#[cfg(windows)]
+use super::os::OsScreenshot;
+use super::{CaptureBackend, CaptureError, Monitor, Rectangle, Screenshot};
+
+use custom_error::custom_error;
+use std::{fs::File, path::Path, path::PathBuf};
+
+custom_error! { pub LoadError
+    Io{source: std::io::Error} = "cannot open the image: {source:?}",
+    Decoding{source: png::DecodingError} = "cannot decode the image: {source:?}",
+}
+
+// "captures" a PNG file, for headless sessions and testing
+pub struct FileBackend {
+    path: PathBuf,
+}
+
+impl FileBackend {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FileBackend { path: path.into() }
+    }
+}
+
+impl CaptureBackend for FileBackend {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn take(&self) -> Result<Screenshot, CaptureError> {
+        let (w, h, data) = load_png(&self.path)
+            .map_err(|e| CaptureError::failed("file", format!("{}: {}", self.path.display(), e)))?;
+
+        let bounds = Rectangle { x: 0, y: 0, w, h };
+
+        Ok(Screenshot {
+            #[cfg(windows)]
+            os: OsScreenshot::from_data(&data, bounds),
+
+            bounds,
+            windows: Vec::new(),
+            monitors: vec![Monitor::covering(&self.path.display().to_string(), bounds)],
+            cursor: None,
+            data,
+        })
+    }
+}
+
+// loads a PNG file as bottom-up RGB
+pub fn load_png(path: &Path) -> Result<(i32, i32, Vec<u8>), LoadError> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+
+    let (w, h) = (info.width as usize, info.height as usize);
+    let channels = info.color_type.samples();
+    let mut data = Vec::with_capacity(w * h * 3);
+
+    for row in buf.chunks_exact(info.line_size).take(h).rev() {
+        for px in row[..w * channels].chunks_exact(channels) {
+            match channels {
+                1 | 2 => data.extend_from_slice(&[px[0], px[0], px[0]]),
+                _ => data.extend_from_slice(&px[..3]),
+            }
+        }
+    }
+
+    Ok((w as i32, h as i32, data))
+}
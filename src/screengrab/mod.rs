@@ -1,33 +1,225 @@
-use num_traits::Num;
-
-#[cfg_attr(windows, path = "windows.rs")]
-mod os;
-
-#[derive(Debug)]
-pub struct Screenshot {
-    os: os::OsScreenshot,
-
-    pub data: Vec<u8>,
-    pub bounds: Rectangle<i32>,
-    pub windows: Vec<Window>,
-}
-
-#[derive(Debug)]
-pub struct Window {
-    pub title: String,
-    pub bounds: Rectangle<i32>,
-}
-
-#[derive(Debug, PartialEq, Copy, Clone)]
-pub struct Rectangle<T: Num> {
-    pub x: T,
-    pub y: T,
-    pub w: T,
-    pub h: T,
-}
-
-impl<T: Num + Copy> Rectangle<T> {
-    pub fn contains<U: PartialOrd<T>>(&self, x: U, y: U) -> bool {
-        x >= self.x && y >= self.y && x <= (self.x + self.w) && y <= (self.y + self.h)
-    }
-}
+use custom_error::custom_error;
+use num_traits::Num;
+use std::fmt::Display;
+
+use super::image::Image;
+
+mod file;
+mod mode;
+
+#[cfg_attr(windows, path = "windows.rs")]
+#[cfg_attr(unix, path = "unix.rs")]
+mod os;
+
+pub use mode::{CaptureMode, ModeError};
+pub use os::persist_clipboard;
+#[cfg(unix)]
+pub use os::serve_clipboard;
+
+// names of the backends, as given to `--backend`
+pub type BackendName = &'static str;
+
+custom_error! { pub CaptureError
+    NoBackend = "no capture backend fits this session",
+    DisplayUnavailable{backend: BackendName} = "{backend}: cannot connect to the display",
+    PermissionDenied{backend: BackendName} = "{backend}: the screenshot was denied",
+    BackendMissing{backend: BackendName} = "{backend}: not supported by this session",
+    Failed{backend: BackendName, reason: String} = "{backend}: {reason}",
+    // the screenshot is fine, but some windows won't be snapped to
+    PartialWindows{failed: usize, snap: Box<Screenshot>} =
+        @{ format!("{} windows couldn't be listed", failed) },
+}
+
+impl CaptureError {
+    pub fn failed<E: Display>(backend: BackendName, reason: E) -> Self {
+        CaptureError::Failed {
+            backend,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+// a way of taking screenshots, picked at runtime
+pub trait CaptureBackend {
+    fn name(&self) -> BackendName;
+
+    fn take(&self) -> Result<Screenshot, CaptureError>;
+}
+
+// the backends to try, in order: the ones that fit the session, or just the
+// one with the given name ("file:PATH" loads a PNG instead of capturing)
+pub fn backends(name: Option<&str>) -> Option<Vec<Box<dyn CaptureBackend>>> {
+    match name {
+        None => Some(os::detect_backends()),
+        Some(name) => match name.strip_prefix("file:") {
+            Some(path) => Some(vec![Box::new(file::FileBackend::new(path))]),
+            None => os::backend_by_name(name).map(|backend| vec![backend]),
+        },
+    }
+}
+
+#[derive(Debug)]
+pub struct Screenshot {
+    // only the windows clipboard needs it for now
+    #[cfg(windows)]
+    os: os::OsScreenshot,
+
+    // RGB pixels, bottom row first
+    pub data: Vec<u8>,
+    pub bounds: Rectangle<i32>,
+    pub windows: Vec<Window>,
+    pub monitors: Vec<Monitor>,
+    // relative to the virtual screen origin, when the backend knows it
+    pub cursor: Option<(i32, i32)>,
+}
+
+impl Screenshot {
+    // the first backend that works wins
+    pub fn take_with(backends: &[Box<dyn CaptureBackend>]) -> Result<Self, CaptureError> {
+        let mut error = CaptureError::NoBackend;
+
+        for backend in backends {
+            match backend.take() {
+                Ok(snap) => {
+                    // print information about the monitors for debug purposes
+                    if cfg!(debug_assertions) {
+                        for monitor in &snap.monitors {
+                            eprintln!("Monitor {}:", monitor.name);
+                            eprintln!("  Bounds: {:?}", monitor.bounds);
+                            eprintln!("  Scale: {}", monitor.scale);
+                            eprintln!("  Primary: {}", monitor.primary);
+                            eprintln!("  Refresh rate: {:?}", monitor.refresh_rate);
+                        }
+                    }
+
+                    return Ok(snap);
+                }
+
+                // the user said no, don't go around them
+                Err(e @ CaptureError::PermissionDenied { .. }) => return Err(e),
+                Err(e @ CaptureError::PartialWindows { .. }) => return Err(e),
+
+                Err(e) => {
+                    if cfg!(debug_assertions) {
+                        eprintln!("Backend {} failed: {}", backend.name(), e);
+                    }
+
+                    error = e;
+                }
+            }
+        }
+
+        Err(error)
+    }
+
+    // copies a region (relative to the screenshot, top-left origin), clipped
+    // to the screenshot
+    pub fn crop(&self, region: Rectangle<u32>) -> Image {
+        let (w, h) = (self.bounds.w as u32, self.bounds.h as u32);
+
+        let left = region.x.min(w);
+        let top = region.y.min(h);
+        let right = region.x.saturating_add(region.w).min(w);
+        let bottom = region.y.saturating_add(region.h).min(h);
+
+        let mut data = Vec::with_capacity(((right - left) * (bottom - top) * 3) as usize);
+
+        // rows are stored bottom-up, images go top-down
+        for y in top..bottom {
+            let row = ((h - 1 - y) * w * 3) as usize;
+
+            data.extend_from_slice(&self.data[row + left as usize * 3..row + right as usize * 3]);
+        }
+
+        Image {
+            width: right - left,
+            height: bottom - top,
+            data,
+        }
+    }
+
+    // copies a whole monitor, clipped to the screenshot like any region
+    pub fn crop_monitor(&self, monitor: &Monitor) -> Image {
+        let Rectangle { x, y, w, h } = monitor.bounds;
+        let (left, top) = (x.max(0), y.max(0));
+
+        self.crop(Rectangle {
+            x: left as u32,
+            y: top as u32,
+            w: x.saturating_add(w).saturating_sub(left).max(0) as u32,
+            h: y.saturating_add(h).saturating_sub(top).max(0) as u32,
+        })
+    }
+
+    // the topmost window under the center of a region
+    pub fn window_under(&self, region: Rectangle<u32>) -> Option<&Window> {
+        let (x, y) = region.center();
+
+        self.windows.iter().find(|w| w.bounds.contains(x, y))
+    }
+
+    // the monitor under the center of a region
+    pub fn monitor_under(&self, region: Rectangle<u32>) -> Option<&Monitor> {
+        let (x, y) = region.center();
+
+        self.monitors.iter().find(|m| m.bounds.contains(x, y))
+    }
+}
+
+#[derive(Debug)]
+pub struct Window {
+    pub title: String,
+    pub bounds: Rectangle<i32>,
+    // has the keyboard focus
+    pub active: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Monitor {
+    pub name: String,
+    // relative to the virtual screen origin, like window bounds
+    pub bounds: Rectangle<i32>,
+    // physical pixels per logical pixel
+    pub scale: f64,
+    pub primary: bool,
+    // in Hz, when the backend knows it
+    pub refresh_rate: Option<f64>,
+}
+
+impl Monitor {
+    // a single monitor covering the whole screenshot, for backends that
+    // don't know how the screen is laid out
+    fn covering(name: &str, bounds: Rectangle<i32>) -> Self {
+        Monitor {
+            name: name.to_owned(),
+            bounds: Rectangle {
+                x: 0,
+                y: 0,
+                ..bounds
+            },
+            scale: 1.0,
+            primary: true,
+            refresh_rate: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Rectangle<T: Num> {
+    pub x: T,
+    pub y: T,
+    pub w: T,
+    pub h: T,
+}
+
+impl Rectangle<u32> {
+    pub fn center(&self) -> (i32, i32) {
+        ((self.x + self.w / 2) as i32, (self.y + self.h / 2) as i32)
+    }
+}
+
+impl<T: Num + Copy> Rectangle<T> {
+    pub fn contains<U: PartialOrd<T>>(&self, x: U, y: U) -> bool {
+        x >= self.x && y >= self.y && x <= (self.x + self.w) && y <= (self.y + self.h)
+    }
+}
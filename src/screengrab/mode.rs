@@ -0,0 +1,334 @@
+use super::{Monitor, Rectangle, Screenshot};
+use crate::image::Image;
+
+use custom_error::custom_error;
+use regex::Regex;
+
+custom_error! { pub ModeError
+    NoMonitor = "the screenshot has no monitors",
+    NoSuchMonitor{index: usize, count: usize} = "no monitor {index} (there are {count})",
+    UnknownActiveWindow = "the active window is unknown",
+    NoMatchingWindow{pattern: Regex} = "no window title matches \"{pattern}\"",
+    Empty = "the region is outside of the screen",
+}
+
+// what to capture without asking the user
+#[derive(Debug, Clone)]
+pub enum CaptureMode {
+    // every monitor
+    Screen,
+    // relative to the virtual screen origin
+    Region(Rectangle<i32>),
+    // in the order the backend lists them
+    Monitor(usize),
+    // the monitor under the cursor
+    CurrentMonitor,
+    // the focused window
+    ActiveWindow,
+    // the topmost window whose title matches
+    WindowTitle(Regex),
+}
+
+impl CaptureMode {
+    // the region to capture, relative to the screenshot (like the cropper's)
+    pub fn region(&self, snap: &Screenshot) -> Result<Rectangle<u32>, ModeError> {
+        let bounds = match self {
+            CaptureMode::Screen => Rectangle {
+                x: 0,
+                y: 0,
+                ..snap.bounds
+            },
+
+            CaptureMode::Region(region) => *region,
+
+            CaptureMode::Monitor(_) | CaptureMode::CurrentMonitor => {
+                self.monitor(snap)?
+                    .expect("a monitor mode picks a monitor")
+                    .bounds
+            }
+
+            CaptureMode::ActiveWindow => {
+                snap.windows
+                    .iter()
+                    .find(|window| window.active)
+                    .ok_or(ModeError::UnknownActiveWindow)?
+                    .bounds
+            }
+
+            // windows are listed topmost first
+            CaptureMode::WindowTitle(pattern) => {
+                snap.windows
+                    .iter()
+                    .find(|window| pattern.is_match(&window.title))
+                    .ok_or_else(|| ModeError::NoMatchingWindow {
+                        pattern: pattern.clone(),
+                    })?
+                    .bounds
+            }
+        };
+
+        // windows can hang off the screen, and regions come from users
+        let left = bounds.x.max(0);
+        let top = bounds.y.max(0);
+        let right = bounds.x.saturating_add(bounds.w).min(snap.bounds.w);
+        let bottom = bounds.y.saturating_add(bounds.h).min(snap.bounds.h);
+
+        if right <= left || bottom <= top {
+            return Err(ModeError::Empty);
+        }
+
+        Ok(Rectangle {
+            x: left as u32,
+            y: top as u32,
+            w: (right - left) as u32,
+            h: (bottom - top) as u32,
+        })
+    }
+
+    // the image to capture: whole monitors, or the region
+    pub fn crop(&self, snap: &Screenshot) -> Result<Image, ModeError> {
+        match self.monitor(snap)? {
+            Some(monitor) => Ok(snap.crop_monitor(monitor)),
+            None => Ok(snap.crop(self.region(snap)?)),
+        }
+    }
+
+    // the monitor to capture, None when it's something else
+    fn monitor<'s>(&self, snap: &'s Screenshot) -> Result<Option<&'s Monitor>, ModeError> {
+        match self {
+            CaptureMode::Monitor(index) => {
+                snap.monitors
+                    .get(*index)
+                    .map(Some)
+                    .ok_or(ModeError::NoSuchMonitor {
+                        index: *index,
+                        count: snap.monitors.len(),
+                    })
+            }
+
+            CaptureMode::CurrentMonitor => {
+                let under_cursor = snap.cursor.and_then(|(x, y)| {
+                    snap.monitors
+                        .iter()
+                        .find(|monitor| monitor.bounds.contains(x, y))
+                });
+
+                // the backend may not know where the cursor is
+                under_cursor
+                    .or_else(|| snap.monitors.iter().find(|monitor| monitor.primary))
+                    .or_else(|| snap.monitors.first())
+                    .map(Some)
+                    .ok_or(ModeError::NoMonitor)
+            }
+
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(windows)]
+    use crate::screengrab::os::OsScreenshot;
+    use crate::screengrab::Window;
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32> {
+        Rectangle { x, y, w, h }
+    }
+
+    fn window(title: &str, bounds: Rectangle<i32>, active: bool) -> Window {
+        Window {
+            title: title.to_owned(),
+            bounds,
+            active,
+        }
+    }
+
+    fn monitor(name: &str, bounds: Rectangle<i32>, primary: bool) -> Monitor {
+        Monitor {
+            name: name.to_owned(),
+            bounds,
+            scale: 1.0,
+            primary,
+            refresh_rate: None,
+        }
+    }
+
+    // two 100x50 monitors side by side
+    fn screenshot() -> Screenshot {
+        let data = vec![0; 200 * 50 * 3];
+        let bounds = rect(0, 0, 200, 50);
+
+        Screenshot {
+            #[cfg(windows)]
+            os: OsScreenshot::from_data(&data, bounds),
+
+            data,
+            bounds,
+            windows: vec![
+                window("editor", rect(-10, -5, 50, 30), false),
+                window("terminal", rect(180, 40, 100, 100), true),
+                window("offscreen", rect(300, 0, 10, 10), false),
+            ],
+            monitors: vec![
+                monitor("left", rect(0, 0, 100, 50), false),
+                monitor("right", rect(100, 0, 100, 50), true),
+            ],
+            cursor: Some((20, 10)),
+        }
+    }
+
+    fn region(mode: CaptureMode) -> Result<Rectangle<u32>, ModeError> {
+        mode.region(&screenshot())
+    }
+
+    #[test]
+    fn clips_regions_to_the_screen() {
+        let whole = Rectangle {
+            x: 0,
+            y: 0,
+            w: 200,
+            h: 50,
+        };
+
+        assert_eq!(region(CaptureMode::Screen).unwrap(), whole);
+        assert_eq!(
+            region(CaptureMode::Region(rect(10, 20, 30, 10))).unwrap(),
+            Rectangle {
+                x: 10,
+                y: 20,
+                w: 30,
+                h: 10
+            }
+        );
+        assert_eq!(
+            region(CaptureMode::Region(rect(-50, -50, 1000, 1000))).unwrap(),
+            whole
+        );
+        assert!(matches!(
+            region(CaptureMode::Region(rect(200, 0, 10, 10))),
+            Err(ModeError::Empty)
+        ));
+    }
+
+    #[test]
+    fn doesnt_overflow_on_huge_regions() {
+        assert!(matches!(
+            region(CaptureMode::Region(rect(i32::MAX, 0, 10, 10))),
+            Err(ModeError::Empty)
+        ));
+        assert_eq!(
+            region(CaptureMode::Region(rect(190, -10, i32::MAX, i32::MAX))).unwrap(),
+            Rectangle {
+                x: 190,
+                y: 0,
+                w: 10,
+                h: 50
+            }
+        );
+    }
+
+    #[test]
+    fn clips_windows_hanging_off_the_screen() {
+        assert_eq!(
+            region(CaptureMode::WindowTitle(Regex::new("^edit").unwrap())).unwrap(),
+            Rectangle {
+                x: 0,
+                y: 0,
+                w: 40,
+                h: 25
+            }
+        );
+        assert_eq!(
+            region(CaptureMode::ActiveWindow).unwrap(),
+            Rectangle {
+                x: 180,
+                y: 40,
+                w: 20,
+                h: 10
+            }
+        );
+        assert!(matches!(
+            region(CaptureMode::WindowTitle(Regex::new("offscreen").unwrap())),
+            Err(ModeError::Empty)
+        ));
+        assert!(matches!(
+            region(CaptureMode::WindowTitle(Regex::new("browser").unwrap())),
+            Err(ModeError::NoMatchingWindow { .. })
+        ));
+    }
+
+    #[test]
+    fn picks_monitors() {
+        let right = Rectangle {
+            x: 100,
+            y: 0,
+            w: 100,
+            h: 50,
+        };
+
+        assert_eq!(region(CaptureMode::Monitor(1)).unwrap(), right);
+        assert!(matches!(
+            region(CaptureMode::Monitor(2)),
+            Err(ModeError::NoSuchMonitor { index: 2, count: 2 })
+        ));
+
+        // under the cursor, or the primary one when it's unknown
+        assert_eq!(
+            region(CaptureMode::CurrentMonitor).unwrap(),
+            Rectangle {
+                x: 0,
+                y: 0,
+                w: 100,
+                h: 50
+            }
+        );
+        let mut snap = screenshot();
+        snap.cursor = None;
+        assert_eq!(CaptureMode::CurrentMonitor.region(&snap).unwrap(), right);
+    }
+
+    #[test]
+    fn crops_whole_monitors() {
+        let mut snap = screenshot();
+
+        // every pixel holds its column
+        snap.data = (0..200 * 50).flat_map(|i| [(i % 200) as u8; 3]).collect();
+
+        let left = CaptureMode::CurrentMonitor.crop(&snap).unwrap();
+        let right = CaptureMode::Monitor(1).crop(&snap).unwrap();
+
+        assert_eq!((left.width, left.height), (100, 50));
+        assert_eq!(&left.data[..6], [0, 0, 0, 1, 1, 1]);
+        assert_eq!((right.width, right.height), (100, 50));
+        assert_eq!(&right.data[..6], [100, 100, 100, 101, 101, 101]);
+        assert_eq!(
+            right.data,
+            snap.crop(region(CaptureMode::Monitor(1)).unwrap()).data
+        );
+        assert!(matches!(
+            CaptureMode::Monitor(2).crop(&snap),
+            Err(ModeError::NoSuchMonitor { index: 2, count: 2 })
+        ));
+
+        // other modes crop their region
+        let window = CaptureMode::ActiveWindow.crop(&snap).unwrap();
+        assert_eq!((window.width, window.height), (20, 10));
+        assert_eq!(window.data[0], 180);
+    }
+
+    #[test]
+    fn clips_monitors_to_the_screenshot() {
+        let mut snap = screenshot();
+        let image = snap.crop_monitor(&monitor("hanging", rect(-10, 40, 30, 20), false));
+
+        assert_eq!((image.width, image.height), (20, 10));
+
+        snap.monitors.clear();
+        assert!(matches!(
+            CaptureMode::CurrentMonitor.crop(&snap),
+            Err(ModeError::NoMonitor)
+        ));
+    }
+}
@@ -0,0 +1,305 @@
+use super::{file, CaptureBackend, CaptureError, Monitor, Rectangle, Screenshot};
+
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::Duration,
+};
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+};
+
+const BACKEND: &str = "portal";
+const DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PATH: &str = "/org/freedesktop/portal/desktop";
+
+// long enough for the user to answer a permission dialog
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(120);
+
+// the daemon asks the portal more than once, every request needs its own token
+static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+pub struct PortalBackend;
+
+impl CaptureBackend for PortalBackend {
+    fn name(&self) -> &'static str {
+        BACKEND
+    }
+
+    fn take(&self) -> Result<Screenshot, CaptureError> {
+        take()
+    }
+}
+
+fn dbus_error(e: zbus::Error) -> CaptureError {
+    match e {
+        // nobody implements the screenshot portal here
+        zbus::Error::MethodError(ref name, _, _)
+            if [
+                "org.freedesktop.DBus.Error.ServiceUnknown",
+                "org.freedesktop.DBus.Error.UnknownInterface",
+                "org.freedesktop.DBus.Error.UnknownMethod",
+                "org.freedesktop.DBus.Error.UnknownObject",
+            ]
+            .contains(&name.as_str()) =>
+        {
+            CaptureError::BackendMissing { backend: BACKEND }
+        }
+        e => CaptureError::failed(BACKEND, e),
+    }
+}
+
+fn take() -> Result<Screenshot, CaptureError> {
+    let conn =
+        Connection::session().map_err(|_| CaptureError::DisplayUnavailable { backend: BACKEND })?;
+
+    take_on(&conn, RESPONSE_TIMEOUT)
+}
+
+// asks org.freedesktop.portal.Screenshot for the whole screen
+fn take_on(conn: &Connection, timeout: Duration) -> Result<Screenshot, CaptureError> {
+    let portal = Proxy::new(conn, DESTINATION, PATH, "org.freedesktop.portal.Screenshot")
+        .map_err(dbus_error)?;
+
+    // the request object path is predictable from our unique name and the
+    // handle token, so we can subscribe to its Response before calling
+    let token = format!(
+        "screensnap{}_{}",
+        std::process::id(),
+        REQUESTS.fetch_add(1, Ordering::Relaxed)
+    );
+    let sender = conn
+        .unique_name()
+        .map(|name| name.trim_start_matches(':').replace('.', "_"))
+        .unwrap_or_default();
+
+    let request = Proxy::new(
+        conn,
+        DESTINATION,
+        format!("{}/request/{}/{}", PATH, sender, token),
+        "org.freedesktop.portal.Request",
+    )
+    .map_err(dbus_error)?;
+
+    let mut responses = request.receive_signal("Response").map_err(dbus_error)?;
+
+    let mut options = HashMap::new();
+    options.insert("handle_token", Value::from(token.as_str()));
+    options.insert("interactive", Value::from(false));
+
+    let _: OwnedObjectPath = portal
+        .call("Screenshot", &("", options))
+        .map_err(dbus_error)?;
+
+    // wait for the user (or the portal) to answer, the iterator can't time
+    // out by itself so it waits on a thread of its own (left behind if the
+    // portal never answers)
+    let (answer, answered) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = answer.send(responses.next());
+    });
+
+    let response = answered
+        .recv_timeout(timeout)
+        .map_err(|_| CaptureError::failed(BACKEND, "the portal didn't answer in time"))?
+        .ok_or_else(|| CaptureError::failed(BACKEND, "no response from the portal"))?;
+    let (code, results): (u32, HashMap<String, OwnedValue>) =
+        response.body().deserialize().map_err(dbus_error)?;
+
+    match code {
+        0 => (),
+        1 => return Err(CaptureError::PermissionDenied { backend: BACKEND }),
+        _ => return Err(CaptureError::failed(BACKEND, "the portal couldn't take it")),
+    }
+
+    let uri = results
+        .get("uri")
+        .and_then(|uri| String::try_from(uri.clone()).ok())
+        .ok_or_else(|| CaptureError::failed(BACKEND, "no uri in the response"))?;
+
+    let path = uri_to_path(&uri)
+        .ok_or_else(|| CaptureError::failed(BACKEND, format!("unexpected uri: {}", uri)))?;
+    let (w, h, data) = file::load_png(&path).map_err(|e| CaptureError::failed(BACKEND, e))?;
+
+    // the portal saved it just for us, don't leave it lying around
+    let _ = std::fs::remove_file(&path);
+
+    if cfg!(debug_assertions) {
+        eprintln!("================================");
+        eprintln!("Portal screenshot: {} ({}x{})", path.display(), w, h);
+    }
+
+    // the portal doesn't tell where the monitors are
+    let bounds = Rectangle { x: 0, y: 0, w, h };
+
+    Ok(Screenshot {
+        bounds,
+        windows: Vec::new(),
+        monitors: vec![Monitor::covering("screen", bounds)],
+        cursor: None,
+        data,
+    })
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut path = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+
+    // percent-decoding
+    while i < encoded.len() {
+        if encoded[i] == b'%' {
+            let hex = std::str::from_utf8(encoded.get(i + 1..i + 3)?).ok()?;
+            path.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            path.push(encoded[i]);
+            i += 1;
+        }
+    }
+
+    Some(PathBuf::from(String::from_utf8(path).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::private_bus::PrivateBus;
+
+    use std::fs;
+    use zbus::{interface, message::Header, zvariant::ObjectPath};
+
+    // answers every request with the same response, or never with None
+    struct MockPortal {
+        code: Option<u32>,
+        uri: String,
+    }
+
+    #[interface(name = "org.freedesktop.portal.Screenshot")]
+    impl MockPortal {
+        async fn screenshot(
+            &self,
+            #[zbus(header)] header: Header<'_>,
+            #[zbus(connection)] conn: &zbus::Connection,
+            _parent_window: &str,
+            options: HashMap<String, OwnedValue>,
+        ) -> OwnedObjectPath {
+            let sender = header.sender().unwrap().to_string();
+            let token = String::try_from(options["handle_token"].clone()).unwrap();
+
+            let path = format!(
+                "{}/request/{}/{}",
+                PATH,
+                sender.trim_start_matches(':').replace('.', "_"),
+                token
+            );
+            let path = ObjectPath::try_from(path).unwrap();
+
+            if let Some(code) = self.code {
+                let mut results = HashMap::new();
+                results.insert("uri", Value::from(self.uri.as_str()));
+
+                conn.emit_signal(
+                    Some(sender.as_str()),
+                    &path,
+                    "org.freedesktop.portal.Request",
+                    "Response",
+                    &(code, results),
+                )
+                .await
+                .unwrap();
+            }
+
+            path.into()
+        }
+    }
+
+    // takes a screenshot with the portal answering `code`
+    fn take_with_response(name: &str, code: Option<u32>) -> Result<Screenshot, CaptureError> {
+        let dir = std::env::temp_dir().join(format!("screensnap-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let bus = PrivateBus::start(&dir);
+
+        // a 3x2 image, with a space in its name to percent-encode
+        let path = dir.join("shot 1.png");
+        let mut encoder = png::Encoder::new(fs::File::create(&path).unwrap(), 3, 2);
+        encoder.set_color(png::ColorType::Rgb);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[7u8; 3 * 2 * 3]).unwrap();
+        writer.finish().unwrap();
+
+        let uri = format!("file://{}", path.display()).replace(' ', "%20");
+
+        let _portal = bus
+            .builder()
+            .name(DESTINATION)
+            .unwrap()
+            .serve_at(PATH, MockPortal { code, uri })
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let result = take_on(&bus.connect(), Duration::from_secs(1));
+        let _ = fs::remove_dir_all(&dir);
+
+        result
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon, run with --ignored"]
+    fn loads_the_screenshot_the_portal_saved() {
+        let snap = take_with_response("portal-ok", Some(0)).unwrap();
+
+        assert_eq!((snap.bounds.w, snap.bounds.h), (3, 2));
+        assert_eq!(snap.data, [7u8; 3 * 2 * 3]);
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon, run with --ignored"]
+    fn reports_a_denial() {
+        assert!(matches!(
+            take_with_response("portal-denied", Some(1)),
+            Err(CaptureError::PermissionDenied { backend: BACKEND })
+        ));
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon, run with --ignored"]
+    fn gives_up_on_a_silent_portal() {
+        assert!(matches!(
+            take_with_response("portal-silent", None),
+            Err(CaptureError::Failed { .. })
+        ));
+    }
+
+    #[test]
+    fn decodes_file_uris() {
+        assert_eq!(
+            uri_to_path("file:///home/me/Pictures/Screenshot%20from%202024.png"),
+            Some(PathBuf::from("/home/me/Pictures/Screenshot from 2024.png"))
+        );
+        assert_eq!(
+            uri_to_path("file:///tmp/%C3%A9t%C3%A9.png"),
+            Some(PathBuf::from("/tmp/été.png"))
+        );
+        assert_eq!(
+            uri_to_path("file:///tmp/plain.png"),
+            Some(PathBuf::from("/tmp/plain.png"))
+        );
+
+        // not a file, cut short, not hex, not UTF-8
+        assert_eq!(uri_to_path("https://example.com/shot.png"), None);
+        assert_eq!(uri_to_path("file:///tmp/shot%2"), None);
+        assert_eq!(uri_to_path("file:///tmp/shot%zz.png"), None);
+        assert_eq!(uri_to_path("file:///tmp/%ff.png"), None);
+    }
+}
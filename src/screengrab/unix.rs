@@ -0,0 +1,66 @@
+use super::{file, CaptureBackend, CaptureError, Monitor, Rectangle, Screenshot, Window};
+
+use std::path::{Path, PathBuf};
+
+mod clipboard;
+mod portal;
+mod wlr;
+mod x11;
+
+// the backends that make sense for the current session, best first
+pub fn detect_backends() -> Vec<Box<dyn CaptureBackend>> {
+    let mut backends: Vec<Box<dyn CaptureBackend>> = Vec::new();
+
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        backends.push(Box::new(wlr::WlrBackend));
+
+        // GNOME and KDE only allow screenshots through the desktop portal
+        backends.push(Box::new(portal::PortalBackend));
+    }
+
+    // XWayland can't see native wayland windows, so it comes last
+    if std::env::var_os("DISPLAY").is_some() {
+        backends.push(Box::new(x11::X11Backend));
+    }
+
+    backends
+}
+
+pub fn backend_by_name(name: &str) -> Option<Box<dyn CaptureBackend>> {
+    match name {
+        "x11" => Some(Box::new(x11::X11Backend)),
+        "wlr" => Some(Box::new(wlr::WlrBackend)),
+        "portal" => Some(Box::new(portal::PortalBackend)),
+        _ => None,
+    }
+}
+
+impl Screenshot {
+    // apps that paste files get the saved one, when there's one
+    pub fn copy_to_clipboard(&self, region: Rectangle<u32>, saved: Option<&Path>) {
+        // URIs need absolute paths
+        let saved = saved.and_then(|path| path.canonicalize().ok());
+
+        if let Err(e) = clipboard::copy(self.crop(region), saved) {
+            eprintln!("cannot copy to the clipboard: {}", e);
+        }
+    }
+}
+
+// hands the clipboard over to a background process before exiting
+pub fn persist_clipboard() {
+    if let Err(e) = clipboard::persist() {
+        eprintln!("the clipboard won't outlive screensnap: {}", e);
+    }
+}
+
+// that background process, see `persist_clipboard`
+pub fn serve_clipboard(saved: Option<PathBuf>) -> bool {
+    match clipboard::serve(saved) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("cannot serve the clipboard: {}", e);
+            false
+        }
+    }
+}
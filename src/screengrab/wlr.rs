@@ -0,0 +1,745 @@
+use super::{CaptureBackend, CaptureError, Monitor, Rectangle, Screenshot};
+
+use std::{
+    ffi::CStr, fs::File, os::unix::fs::FileExt, os::unix::io::AsFd, os::unix::io::FromRawFd,
+};
+use wayland_client::{
+    delegate_noop,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_output::{self, Transform, WlOutput},
+        wl_registry::WlRegistry,
+        wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
+    },
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols::xdg::xdg_output::zv1::client::{
+    zxdg_output_manager_v1::ZxdgOutputManagerV1,
+    zxdg_output_v1::{self, ZxdgOutputV1},
+};
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+};
+
+// what we know about a wl_output
+#[derive(Debug, Default)]
+struct Output {
+    // from wl_output (physical pixels)
+    name: Option<String>,
+    position: (i32, i32),
+    mode: (i32, i32),
+    // how the compositor turns the output's content to show it
+    transform: Option<Transform>,
+    refresh: Option<i32>,
+    scale: Option<i32>,
+
+    // from xdg_output (compositor space)
+    logical_position: Option<(i32, i32)>,
+    logical_size: Option<(i32, i32)>,
+}
+
+impl Output {
+    fn logical_bounds(&self) -> Rectangle<i32> {
+        let (x, y) = self.logical_position.unwrap_or(self.position);
+        let (w, h) = self
+            .logical_size
+            .unwrap_or_else(|| match quarter_turn(self.transform) {
+                true => (self.mode.1, self.mode.0),
+                false => self.mode,
+            });
+
+        Rectangle { x, y, w, h }
+    }
+
+    fn monitor(&self, i: usize, x: i32, y: i32) -> Monitor {
+        let bounds = self.logical_bounds();
+
+        // fractional scales only show in the logical size (compare the
+        // longest sides, rotated outputs have their mode swapped)
+        let scale = match self.logical_size {
+            Some((w, h)) if w.max(h) > 0 => self.mode.0.max(self.mode.1) as f64 / w.max(h) as f64,
+            _ => self.scale.unwrap_or(1) as f64,
+        };
+
+        Monitor {
+            name: self.name.clone().unwrap_or_else(|| format!("output {}", i)),
+            bounds: Rectangle {
+                x: bounds.x - x,
+                y: bounds.y - y,
+                ..bounds
+            },
+            scale,
+            // wayland has no primary output
+            primary: false,
+            // wl_output gives it in mHz
+            refresh_rate: self
+                .refresh
+                .filter(|&refresh| refresh > 0)
+                .map(|refresh| refresh as f64 / 1000.0),
+        }
+    }
+}
+
+// state of the screencopy frame being captured
+#[derive(Debug, Default)]
+struct Frame {
+    // every shm buffer the compositor offers: format, width, height, stride
+    buffers: Vec<(WEnum<wl_shm::Format>, u32, u32, u32)>,
+    buffer_done: bool,
+    y_invert: bool,
+    ready: bool,
+    failed: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    outputs: Vec<Output>,
+    frame: Frame,
+}
+
+// a captured output, RGB, top row first
+struct Capture {
+    w: usize,
+    h: usize,
+    data: Vec<u8>,
+}
+
+const BACKEND: &str = "wlr";
+
+pub struct WlrBackend;
+
+impl CaptureBackend for WlrBackend {
+    fn name(&self) -> &'static str {
+        BACKEND
+    }
+
+    fn take(&self) -> Result<Screenshot, CaptureError> {
+        take()
+    }
+}
+
+fn take() -> Result<Screenshot, CaptureError> {
+    let unavailable = |_| CaptureError::DisplayUnavailable { backend: BACKEND };
+    let missing = |_| CaptureError::BackendMissing { backend: BACKEND };
+
+    let conn = Connection::connect_to_env().map_err(unavailable)?;
+    let (globals, mut queue) =
+        registry_queue_init::<State>(&conn).map_err(|e| CaptureError::failed(BACKEND, e))?;
+    let qh = queue.handle();
+
+    // only wlroots-based compositors implement screencopy
+    let shm: WlShm = globals.bind(&qh, 1..=1, ()).map_err(missing)?;
+    let screencopy: ZwlrScreencopyManagerV1 = globals.bind(&qh, 1..=3, ()).map_err(missing)?;
+    let xdg_output_manager: Option<ZxdgOutputManagerV1> = globals.bind(&qh, 2..=3, ()).ok();
+
+    // bind every output, the user data is its index in State::outputs
+    let outputs: Vec<WlOutput> = globals
+        .contents()
+        .clone_list()
+        .iter()
+        .filter(|global| global.interface == WlOutput::interface().name)
+        .enumerate()
+        .map(|(i, global)| {
+            globals
+                .registry()
+                .bind(global.name, global.version.min(4), &qh, i)
+        })
+        .collect();
+
+    let mut state = State {
+        outputs: outputs.iter().map(|_| Output::default()).collect(),
+        frame: Frame::default(),
+    };
+
+    if let Some(manager) = &xdg_output_manager {
+        for (i, output) in outputs.iter().enumerate() {
+            manager.get_xdg_output(output, &qh, i);
+        }
+    }
+
+    // get the output geometries
+    queue
+        .roundtrip(&mut state)
+        .map_err(|e| CaptureError::failed(BACKEND, e))?;
+
+    if state.outputs.is_empty() {
+        return Err(CaptureError::failed(BACKEND, "no outputs"));
+    }
+
+    // get virtual screen bounds (covers all monitors)
+    let bounds = || state.outputs.iter().map(|o| o.logical_bounds());
+
+    let x = bounds().map(|b| b.x).min().unwrap_or(0);
+    let y = bounds().map(|b| b.y).min().unwrap_or(0);
+    let right = bounds().map(|b| b.x + b.w).max().unwrap_or(0);
+    let bottom = bounds().map(|b| b.y + b.h).max().unwrap_or(0);
+    let (w, h) = (right - x, bottom - y);
+
+    if cfg!(debug_assertions) {
+        eprintln!("================================");
+        eprintln!("Virtual screen bounds: {}, {}, {}, {}", x, y, w, h);
+    }
+
+    // compose every output at its logical position
+    let mut data = vec![0u8; (w * h * 3) as usize];
+
+    for (i, output) in outputs.iter().enumerate() {
+        let capture = capture_output(&mut queue, &mut state, &shm, &screencopy, output)?
+            .transformed(state.outputs[i].transform);
+        let bounds = state.outputs[i].logical_bounds();
+
+        blit(
+            &capture,
+            &mut data,
+            w as usize,
+            h as usize,
+            Rectangle {
+                x: bounds.x - x,
+                y: bounds.y - y,
+                ..bounds
+            },
+        );
+    }
+
+    let monitors = state
+        .outputs
+        .iter()
+        .enumerate()
+        .map(|(i, output)| output.monitor(i, x, y))
+        .collect();
+
+    Ok(Screenshot {
+        bounds: Rectangle { x, y, w, h },
+        // wayland doesn't let clients see each other
+        windows: Vec::new(),
+        monitors,
+        cursor: None,
+        data,
+    })
+}
+
+fn capture_output(
+    queue: &mut EventQueue<State>,
+    state: &mut State,
+    shm: &WlShm,
+    screencopy: &ZwlrScreencopyManagerV1,
+    output: &WlOutput,
+) -> Result<Capture, CaptureError> {
+    let qh = queue.handle();
+    let failed = || CaptureError::failed(BACKEND, "cannot capture an output");
+
+    state.frame = Frame::default();
+    let frame = screencopy.capture_output(0, output, &qh, ());
+
+    // wait for the compositor to describe the buffers it takes
+    // (version 3 can offer several, then sends buffer_done)
+    while state.frame.buffers.is_empty() || (frame.version() >= 3 && !state.frame.buffer_done) {
+        if state.frame.failed {
+            frame.destroy();
+            return Err(failed());
+        }
+
+        queue.blocking_dispatch(state).map_err(|_| failed())?;
+    }
+
+    // the first one we can read
+    let offered = &state.frame.buffers;
+    let readable = offered.iter().find_map(|&(format, w, h, stride)| {
+        let format = format.into_result().ok()?;
+        Some((format, w, h, stride, pixel_format(format)?))
+    });
+
+    let (format, w, h, stride, (bytes, to_rgb)) = match readable {
+        Some(buffer) => buffer,
+        None => {
+            frame.destroy();
+
+            let formats: Vec<String> = offered
+                .iter()
+                .map(|(format, ..)| match format {
+                    WEnum::Value(format) => format!("{:?}", format),
+                    WEnum::Unknown(code) => format!("{:#x}", code),
+                })
+                .collect();
+
+            return Err(CaptureError::failed(
+                BACKEND,
+                format!("unsupported buffer formats: {}", formats.join(", ")),
+            ));
+        }
+    };
+    let size = (stride * h) as usize;
+
+    let file = memfd(size).ok_or_else(failed)?;
+    let pool = shm.create_pool(file.as_fd(), size as i32, &qh, ());
+    let buffer = pool.create_buffer(0, w as i32, h as i32, stride as i32, format, &qh, ());
+
+    frame.copy(&buffer);
+
+    while !state.frame.ready && !state.frame.failed {
+        queue.blocking_dispatch(state).map_err(|_| failed())?;
+    }
+
+    let mut pixels = vec![0u8; size];
+    let read = file.read_exact_at(&mut pixels, 0);
+
+    frame.destroy();
+    buffer.destroy();
+    pool.destroy();
+
+    if state.frame.failed || read.is_err() {
+        return Err(failed());
+    }
+
+    let (w, h, stride) = (w as usize, h as usize, stride as usize);
+    let mut data = Vec::with_capacity(w * h * 3);
+
+    for row in 0..h {
+        let src_row = if state.frame.y_invert {
+            h - 1 - row
+        } else {
+            row
+        };
+        let line = &pixels[src_row * stride..src_row * stride + w * bytes];
+
+        for px in line.chunks_exact(bytes) {
+            data.extend_from_slice(&to_rgb(px));
+        }
+    }
+
+    Ok(Capture { w, h, data })
+}
+
+type ToRgb = fn(&[u8]) -> [u8; 3];
+
+// the size of a pixel in a wl_shm format, and how to read it as RGB (the
+// formats are little-endian, so the channels are reversed in memory)
+fn pixel_format(format: wl_shm::Format) -> Option<(usize, ToRgb)> {
+    use wl_shm::Format;
+
+    // 10 bits per channel, keep the 8 most significant ones
+    fn xrgb2101010(px: &[u8]) -> [u8; 3] {
+        let v = u32::from_le_bytes([px[0], px[1], px[2], px[3]]);
+        [(v >> 22) as u8, (v >> 12) as u8, (v >> 2) as u8]
+    }
+    fn xbgr2101010(px: &[u8]) -> [u8; 3] {
+        let [b, g, r] = xrgb2101010(px);
+        [r, g, b]
+    }
+
+    Some(match format {
+        Format::Argb8888 | Format::Xrgb8888 => (4, |px| [px[2], px[1], px[0]]),
+        Format::Abgr8888 | Format::Xbgr8888 => (4, |px| [px[0], px[1], px[2]]),
+        Format::Rgb888 => (3, |px| [px[2], px[1], px[0]]),
+        Format::Bgr888 => (3, |px| [px[0], px[1], px[2]]),
+        Format::Argb2101010 | Format::Xrgb2101010 => (4, xrgb2101010),
+        Format::Abgr2101010 | Format::Xbgr2101010 => (4, xbgr2101010),
+        _ => return None,
+    })
+}
+
+// 90 and 270 degree transforms swap the width and height
+fn quarter_turn(transform: Option<Transform>) -> bool {
+    matches!(
+        transform,
+        Some(Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270)
+    )
+}
+
+impl Capture {
+    // the frame comes as the output's buffer: undo the output transform, so
+    // that it looks the way the user sees it (like grim, the rotation is
+    // counter-clockwise, after flipping around the vertical axis)
+    fn transformed(self, transform: Option<Transform>) -> Capture {
+        let (flipped, turns) = match transform {
+            None | Some(Transform::Normal) => return self,
+            Some(Transform::_90) => (false, 1),
+            Some(Transform::_180) => (false, 2),
+            Some(Transform::_270) => (false, 3),
+            Some(Transform::Flipped) => (true, 0),
+            Some(Transform::Flipped90) => (true, 1),
+            Some(Transform::Flipped180) => (true, 2),
+            Some(Transform::Flipped270) => (true, 3),
+            Some(_) => return self,
+        };
+
+        let (w, h) = match turns % 2 {
+            0 => (self.w, self.h),
+            _ => (self.h, self.w),
+        };
+        let mut data = Vec::with_capacity(self.data.len());
+
+        for y in 0..h {
+            for x in 0..w {
+                // where (x, y) comes from in the frame
+                let (sx, sy) = match turns {
+                    0 => (x, y),
+                    1 => (self.w - 1 - y, x),
+                    2 => (self.w - 1 - x, self.h - 1 - y),
+                    _ => (y, self.h - 1 - x),
+                };
+                let sx = match flipped {
+                    true => self.w - 1 - sx,
+                    false => sx,
+                };
+
+                let src = (sy * self.w + sx) * 3;
+                data.extend_from_slice(&self.data[src..src + 3]);
+            }
+        }
+
+        Capture { w, h, data }
+    }
+}
+
+// copies a capture into the bottom-up screenshot buffer, scaling it to the
+// logical size of its output (nearest neighbour, for HiDPI outputs)
+fn blit(capture: &Capture, data: &mut [u8], w: usize, h: usize, dest: Rectangle<i32>) {
+    for dy in 0..dest.h.max(0) as usize {
+        let y = dest.y as usize + dy;
+
+        if y >= h {
+            break;
+        }
+
+        let sy = dy * capture.h / dest.h as usize;
+        let row = (h - 1 - y) * w;
+
+        for dx in 0..dest.w.max(0) as usize {
+            let x = dest.x as usize + dx;
+
+            if x >= w {
+                break;
+            }
+
+            let sx = dx * capture.w / dest.w as usize;
+            let src = (sy * capture.w + sx) * 3;
+            let dst = (row + x) * 3;
+
+            data[dst..dst + 3].copy_from_slice(&capture.data[src..src + 3]);
+        }
+    }
+}
+
+// anonymous file to share the buffer with the compositor
+fn memfd(size: usize) -> Option<File> {
+    let name = CStr::from_bytes_with_nul(b"screensnap\0").unwrap();
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+
+    if fd < 0 {
+        return None;
+    }
+
+    let file = unsafe { File::from_raw_fd(fd) };
+    file.set_len(size as u64).ok()?;
+
+    Some(file)
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: <WlRegistry as Proxy>::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlOutput, usize> for State {
+    fn event(
+        state: &mut Self,
+        _: &WlOutput,
+        event: wl_output::Event,
+        &i: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let output = &mut state.outputs[i];
+
+        match event {
+            wl_output::Event::Geometry {
+                x, y, transform, ..
+            } => {
+                output.position = (x, y);
+                output.transform = transform.into_result().ok();
+            }
+            wl_output::Event::Mode {
+                flags: WEnum::Value(flags),
+                width,
+                height,
+                refresh,
+            } if flags.contains(wl_output::Mode::Current) => {
+                output.mode = (width, height);
+                output.refresh = Some(refresh);
+            }
+            wl_output::Event::Scale { factor } => output.scale = Some(factor),
+            wl_output::Event::Name { name } => output.name = Some(name),
+            _ => (),
+        }
+    }
+}
+
+impl Dispatch<ZxdgOutputV1, usize> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZxdgOutputV1,
+        event: zxdg_output_v1::Event,
+        &i: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let output = &mut state.outputs[i];
+
+        match event {
+            zxdg_output_v1::Event::LogicalPosition { x, y } => {
+                output.logical_position = Some((x, y))
+            }
+            zxdg_output_v1::Event::LogicalSize { width, height } => {
+                output.logical_size = Some((width, height))
+            }
+            // wl_output names (version 4) win, they're the same anyway
+            zxdg_output_v1::Event::Name { name } if output.name.is_none() => {
+                output.name = Some(name)
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer {
+                format,
+                width,
+                height,
+                stride,
+            } => state.frame.buffers.push((format, width, height, stride)),
+            zwlr_screencopy_frame_v1::Event::Flags {
+                flags: WEnum::Value(flags),
+            } => state.frame.y_invert = flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert),
+            zwlr_screencopy_frame_v1::Event::BufferDone => state.frame.buffer_done = true,
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => state.frame.ready = true,
+            zwlr_screencopy_frame_v1::Event::Failed => state.frame.failed = true,
+            _ => (),
+        }
+    }
+}
+
+delegate_noop!(State: ignore WlShm);
+delegate_noop!(State: WlShmPool);
+delegate_noop!(State: ignore WlBuffer);
+delegate_noop!(State: ZxdgOutputManagerV1);
+delegate_noop!(State: ZwlrScreencopyManagerV1);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the pixel at (x, y) from the top of a bottom-up buffer
+    fn pixel(data: &[u8], w: usize, h: usize, x: usize, y: usize) -> [u8; 3] {
+        let i = ((h - 1 - y) * w + x) * 3;
+        [data[i], data[i + 1], data[i + 2]]
+    }
+
+    #[test]
+    fn blits_bottom_up_at_the_output_position() {
+        let capture = Capture {
+            w: 2,
+            h: 1,
+            data: vec![255, 0, 0, 0, 0, 255],
+        };
+
+        let mut data = vec![0u8; 4 * 3 * 3];
+        blit(
+            &capture,
+            &mut data,
+            4,
+            3,
+            Rectangle {
+                x: 1,
+                y: 1,
+                w: 2,
+                h: 1,
+            },
+        );
+
+        assert_eq!(pixel(&data, 4, 3, 1, 1), [255, 0, 0]);
+        assert_eq!(pixel(&data, 4, 3, 2, 1), [0, 0, 255]);
+        assert_eq!(pixel(&data, 4, 3, 0, 1), [0, 0, 0]);
+        assert_eq!(pixel(&data, 4, 3, 1, 0), [0, 0, 0]);
+    }
+
+    #[test]
+    fn blits_hidpi_outputs_at_their_logical_size() {
+        // a 2x scale output: 4x2 pixels for 2x1 logical pixels
+        let capture = Capture {
+            w: 4,
+            h: 2,
+            data: [[10, 10, 10], [20, 20, 20], [30, 30, 30], [40, 40, 40]]
+                .repeat(2)
+                .concat(),
+        };
+
+        let mut data = vec![0u8; 2 * 3];
+        blit(
+            &capture,
+            &mut data,
+            2,
+            1,
+            Rectangle {
+                x: 0,
+                y: 0,
+                w: 2,
+                h: 1,
+            },
+        );
+
+        assert_eq!(data, [10, 10, 10, 30, 30, 30]);
+    }
+
+    #[test]
+    fn blits_rotated_outputs_upright() {
+        // a 2x1 landscape buffer on an output turned 90 degrees, shown as 1x2
+        let capture = Capture {
+            w: 2,
+            h: 1,
+            data: vec![1, 1, 1, 2, 2, 2],
+        }
+        .transformed(Some(Transform::_90));
+
+        let mut data = vec![0u8; 2 * 3];
+        blit(
+            &capture,
+            &mut data,
+            1,
+            2,
+            Rectangle {
+                x: 0,
+                y: 0,
+                w: 1,
+                h: 2,
+            },
+        );
+
+        // the right end of the buffer ends up on top
+        assert_eq!(pixel(&data, 1, 2, 0, 0), [2, 2, 2]);
+        assert_eq!(pixel(&data, 1, 2, 0, 1), [1, 1, 1]);
+    }
+
+    #[test]
+    fn undoes_every_transform() {
+        // 2x2 1 2
+        //     3 4
+        let capture = || Capture {
+            w: 2,
+            h: 2,
+            data: [[1; 3], [2; 3], [3; 3], [4; 3]].concat(),
+        };
+        let pixels = |transform| -> Vec<u8> {
+            let transformed = capture().transformed(Some(transform));
+            transformed.data.chunks(3).map(|px| px[0]).collect()
+        };
+
+        assert_eq!(pixels(Transform::Normal), [1, 2, 3, 4]);
+        assert_eq!(pixels(Transform::_90), [2, 4, 1, 3]);
+        assert_eq!(pixels(Transform::_180), [4, 3, 2, 1]);
+        assert_eq!(pixels(Transform::_270), [3, 1, 4, 2]);
+        assert_eq!(pixels(Transform::Flipped), [2, 1, 4, 3]);
+        assert_eq!(pixels(Transform::Flipped90), [1, 3, 2, 4]);
+        assert_eq!(pixels(Transform::Flipped180), [3, 4, 1, 2]);
+        assert_eq!(pixels(Transform::Flipped270), [4, 2, 3, 1]);
+    }
+
+    #[test]
+    fn reads_shm_formats() {
+        let rgb =
+            |format, px: &[u8]| pixel_format(format).map(|(bytes, to_rgb)| (bytes, to_rgb(px)));
+
+        // 0x00ff8040 and the like, as little-endian bytes
+        assert_eq!(
+            rgb(wl_shm::Format::Xrgb8888, &[0x40, 0x80, 0xff, 0]),
+            Some((4, [0xff, 0x80, 0x40]))
+        );
+        assert_eq!(
+            rgb(wl_shm::Format::Abgr8888, &[0xff, 0x80, 0x40, 0xff]),
+            Some((4, [0xff, 0x80, 0x40]))
+        );
+        assert_eq!(
+            rgb(wl_shm::Format::Bgr888, &[0xff, 0x80, 0x40]),
+            Some((3, [0xff, 0x80, 0x40]))
+        );
+        assert_eq!(
+            rgb(wl_shm::Format::Rgb888, &[0x40, 0x80, 0xff]),
+            Some((3, [0xff, 0x80, 0x40]))
+        );
+
+        // R 0x3ff, G 0x200, B 0x004 in 2:10:10:10
+        let v: u32 = (0x3ff << 20) | (0x200 << 10) | 0x004;
+        assert_eq!(
+            rgb(wl_shm::Format::Xrgb2101010, &v.to_le_bytes()),
+            Some((4, [0xff, 0x80, 0x01]))
+        );
+        let v: u32 = (0x004 << 20) | (0x200 << 10) | 0x3ff;
+        assert_eq!(
+            rgb(wl_shm::Format::Xbgr2101010, &v.to_le_bytes()),
+            Some((4, [0xff, 0x80, 0x01]))
+        );
+
+        assert_eq!(rgb(wl_shm::Format::Nv12, &[0; 4]), None);
+    }
+
+    #[test]
+    fn clips_outputs_to_the_buffer() {
+        let capture = Capture {
+            w: 1,
+            h: 1,
+            data: vec![1, 2, 3],
+        };
+
+        let mut data = vec![0u8; 2 * 2 * 3];
+        blit(
+            &capture,
+            &mut data,
+            2,
+            2,
+            Rectangle {
+                x: 1,
+                y: 1,
+                w: 4,
+                h: 4,
+            },
+        );
+
+        assert_eq!(pixel(&data, 2, 2, 1, 1), [1, 2, 3]);
+        assert_eq!(pixel(&data, 2, 2, 0, 0), [0, 0, 0]);
+    }
+
+    #[test]
+    #[ignore = "needs a wlroots compositor, like `WLR_BACKENDS=headless sway`, run with --ignored"]
+    fn captures_every_output() {
+        let snap = take().unwrap();
+
+        let right = snap.monitors.iter().map(|m| m.bounds.x + m.bounds.w).max();
+        let bottom = snap.monitors.iter().map(|m| m.bounds.y + m.bounds.h).max();
+
+        assert_eq!((right, bottom), (Some(snap.bounds.w), Some(snap.bounds.h)));
+        assert_eq!(
+            snap.data.len(),
+            (snap.bounds.w * snap.bounds.h * 3) as usize
+        );
+    }
+}
@@ -0,0 +1,727 @@
+use super::{CaptureBackend, CaptureError, Monitor, Rectangle, Screenshot, Window};
+
+use std::{os::unix::io::AsRawFd, ptr::null_mut, slice};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        randr::{ConnectionExt as _, GetScreenResourcesCurrentReply, ModeFlag, MonitorInfo},
+        shm::ConnectionExt as _,
+        xproto::{
+            AtomEnum, ConnectionExt as _, ImageFormat, ImageOrder, MapState, Screen, Visualtype,
+        },
+    },
+    rust_connection::RustConnection,
+};
+
+const BACKEND: &str = "x11";
+
+pub struct X11Backend;
+
+impl CaptureBackend for X11Backend {
+    fn name(&self) -> &'static str {
+        BACKEND
+    }
+
+    fn take(&self) -> Result<Screenshot, CaptureError> {
+        take()
+    }
+}
+
+fn take() -> Result<Screenshot, CaptureError> {
+    let (conn, screen_num) =
+        x11rb::connect(None).map_err(|_| CaptureError::DisplayUnavailable { backend: BACKEND })?;
+    let screen = conn.setup().roots[screen_num].clone();
+
+    let mut monitors = get_monitors(&conn, &screen);
+
+    // get virtual screen bounds (covers all monitors)
+    let bounds = || monitors.iter().map(|m| m.bounds);
+
+    let x = bounds().map(|b| b.x).min().unwrap_or(0);
+    let y = bounds().map(|b| b.y).min().unwrap_or(0);
+    let right = bounds().map(|b| b.x + b.w).max().unwrap_or(0);
+    let bottom = bounds().map(|b| b.y + b.h).max().unwrap_or(0);
+    let bounds = Rectangle {
+        x,
+        y,
+        w: right - x,
+        h: bottom - y,
+    };
+    let Rectangle { w, h, .. } = bounds;
+
+    if cfg!(debug_assertions) {
+        eprintln!("================================");
+        eprintln!("Virtual screen bounds: {}, {}, {}, {}", x, y, w, h);
+    }
+
+    // Get pixels from the screen, through shared memory if we can
+    let data = match get_image_shm(&conn, &screen, bounds) {
+        Some(data) => data?,
+        None => get_image(&conn, &screen, bounds)?,
+    };
+
+    // get all windows now
+    let (windows, failed) = get_windows(&conn, screen.root, x, y);
+
+    // monitors are relative to the virtual screen, like windows
+    for monitor in &mut monitors {
+        monitor.bounds.x -= x;
+        monitor.bounds.y -= y;
+    }
+
+    let snap = Screenshot {
+        bounds,
+        windows,
+        monitors,
+        cursor: get_cursor(&conn, screen.root, x, y),
+        data,
+    };
+
+    if failed > 0 {
+        return Err(CaptureError::PartialWindows {
+            failed,
+            snap: Box::new(snap),
+        });
+    }
+
+    Ok(snap)
+}
+
+// the RandR monitors in root coordinates, or the whole root window as a
+// single monitor without RandR
+fn get_monitors(conn: &RustConnection, screen: &Screen) -> Vec<Monitor> {
+    let scale = xft_scale(conn, screen.root);
+
+    let infos = match conn
+        .randr_get_monitors(screen.root, true)
+        .map(|cookie| cookie.reply())
+    {
+        Ok(Ok(reply)) if !reply.monitors.is_empty() => reply.monitors,
+        _ => {
+            return vec![Monitor {
+                name: String::from("default"),
+                bounds: Rectangle {
+                    x: 0,
+                    y: 0,
+                    w: screen.width_in_pixels as i32,
+                    h: screen.height_in_pixels as i32,
+                },
+                scale,
+                primary: true,
+                refresh_rate: None,
+            }]
+        }
+    };
+
+    let resources = conn
+        .randr_get_screen_resources_current(screen.root)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok());
+
+    infos
+        .iter()
+        .map(|info| Monitor {
+            name: conn
+                .get_atom_name(info.name)
+                .ok()
+                .and_then(|cookie| cookie.reply().ok())
+                .map(|reply| String::from_utf8_lossy(&reply.name).into_owned())
+                .unwrap_or_default(),
+            bounds: Rectangle {
+                x: info.x as i32,
+                y: info.y as i32,
+                w: info.width as i32,
+                h: info.height as i32,
+            },
+            scale,
+            primary: info.primary,
+            refresh_rate: resources
+                .as_ref()
+                .and_then(|resources| refresh_rate(conn, resources, info)),
+        })
+        .collect()
+}
+
+// refresh rate of the mode driving the first output of a monitor
+fn refresh_rate(
+    conn: &RustConnection,
+    resources: &GetScreenResourcesCurrentReply,
+    info: &MonitorInfo,
+) -> Option<f64> {
+    let timestamp = resources.config_timestamp;
+
+    let output = conn
+        .randr_get_output_info(*info.outputs.first()?, timestamp)
+        .ok()?
+        .reply()
+        .ok()?;
+    let crtc = conn
+        .randr_get_crtc_info(output.crtc, timestamp)
+        .ok()?
+        .reply()
+        .ok()?;
+    let mode = resources.modes.iter().find(|mode| mode.id == crtc.mode)?;
+
+    let mut lines = mode.vtotal as f64;
+
+    if mode.mode_flags.contains(ModeFlag::DOUBLE_SCAN) {
+        lines *= 2.0;
+    }
+
+    if mode.mode_flags.contains(ModeFlag::INTERLACE) {
+        lines /= 2.0;
+    }
+
+    if mode.htotal == 0 || lines == 0.0 {
+        return None;
+    }
+
+    Some(mode.dot_clock as f64 / (mode.htotal as f64 * lines))
+}
+
+// X11 has no per-monitor scale, but desktops set Xft.dpi for every client
+fn xft_scale(conn: &RustConnection, root: u32) -> f64 {
+    conn.get_property(
+        false,
+        root,
+        AtomEnum::RESOURCE_MANAGER,
+        AtomEnum::STRING,
+        0,
+        u32::MAX,
+    )
+    .ok()
+    .and_then(|cookie| cookie.reply().ok())
+    .and_then(|reply| {
+        String::from_utf8_lossy(&reply.value)
+            .lines()
+            .find_map(|line| line.strip_prefix("Xft.dpi:")?.trim().parse::<f64>().ok())
+    })
+    .map(|dpi| dpi / 96.0)
+    .unwrap_or(1.0)
+}
+
+// the cursor position, relative to the virtual screen origin
+fn get_cursor(conn: &RustConnection, root: u32, x: i32, y: i32) -> Option<(i32, i32)> {
+    let pointer = conn.query_pointer(root).ok()?.reply().ok()?;
+
+    if !pointer.same_screen {
+        return None;
+    }
+
+    Some((pointer.root_x as i32 - x, pointer.root_y as i32 - y))
+}
+
+fn intern(conn: &RustConnection, name: &[u8]) -> Option<u32> {
+    conn.intern_atom(false, name)
+        .ok()?
+        .reply()
+        .ok()
+        .map(|reply| reply.atom)
+}
+
+fn get_property32(conn: &RustConnection, wnd: u32, prop: u32, type_: AtomEnum) -> Vec<u32> {
+    conn.get_property(false, wnd, prop, type_, 0, u32::MAX)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .and_then(|reply| reply.value32().map(|v| v.collect()))
+        .unwrap_or_default()
+}
+
+fn get_title(conn: &RustConnection, wnd: u32, net_wm_name: u32, utf8_string: u32) -> String {
+    // prefer the UTF-8 _NET_WM_NAME over the legacy WM_NAME
+    [
+        (net_wm_name, utf8_string),
+        (AtomEnum::WM_NAME.into(), AtomEnum::STRING.into()),
+    ]
+    .iter()
+    .filter_map(|&(prop, type_)| {
+        conn.get_property(false, wnd, prop, type_, 0, u32::MAX)
+            .ok()?
+            .reply()
+            .ok()
+            .filter(|reply| reply.format == 8 && !reply.value.is_empty())
+    })
+    .map(|reply| String::from_utf8_lossy(&reply.value).into_owned())
+    .next()
+    .unwrap_or_default()
+}
+
+// lists the managed top-level windows, topmost first (like EnumWindows),
+// along with how many of them couldn't be queried
+fn get_windows(conn: &RustConnection, root: u32, x: i32, y: i32) -> (Vec<Window>, usize) {
+    let atoms = [
+        b"_NET_CLIENT_LIST_STACKING" as &[u8],
+        b"_NET_WM_STATE",
+        b"_NET_WM_STATE_HIDDEN",
+        b"_NET_WM_DESKTOP",
+        b"_NET_CURRENT_DESKTOP",
+        b"_NET_FRAME_EXTENTS",
+        b"_NET_WM_NAME",
+        b"UTF8_STRING",
+        b"_NET_ACTIVE_WINDOW",
+    ]
+    .iter()
+    .map(|name| intern(conn, name))
+    .collect::<Option<Vec<_>>>();
+
+    let (
+        net_client_list_stacking,
+        net_wm_state,
+        net_wm_state_hidden,
+        net_wm_desktop,
+        net_current_desktop,
+        net_frame_extents,
+        net_wm_name,
+        utf8_string,
+        net_active_window,
+    ) = match atoms.as_deref() {
+        Some(&[a, b, c, d, e, f, g, h, i]) => (a, b, c, d, e, f, g, h, i),
+        _ => return (Vec::new(), 1),
+    };
+
+    let current_desktop = get_property32(conn, root, net_current_desktop, AtomEnum::CARDINAL)
+        .first()
+        .copied();
+
+    let active = get_property32(conn, root, net_active_window, AtomEnum::WINDOW)
+        .first()
+        .copied();
+
+    // the stacking order is bottom to top
+    let clients = get_property32(conn, root, net_client_list_stacking, AtomEnum::WINDOW);
+
+    let mut windows = Vec::new();
+    let mut failed = 0;
+
+    for &wnd in clients.iter().rev() {
+        // ignore invisible windows
+        let viewable = conn
+            .get_window_attributes(wnd)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .map(|attrs| attrs.map_state == MapState::VIEWABLE);
+
+        if viewable != Some(true) {
+            continue;
+        }
+
+        // ignore minimized windows
+        if get_property32(conn, wnd, net_wm_state, AtomEnum::ATOM).contains(&net_wm_state_hidden) {
+            continue;
+        }
+
+        // ignore windows in other virtual desktops (0xFFFFFFFF = all desktops)
+        let desktop = get_property32(conn, wnd, net_wm_desktop, AtomEnum::CARDINAL)
+            .first()
+            .copied();
+
+        if let (Some(desktop), Some(current)) = (desktop, current_desktop) {
+            if desktop != 0xFFFF_FFFF && desktop != current {
+                continue;
+            }
+        }
+
+        // get the client area bounds, in root coordinates
+        let geometry = match conn
+            .get_geometry(wnd)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+        {
+            Some(geometry) => geometry,
+            None => {
+                failed += 1;
+                continue;
+            }
+        };
+
+        let origin = match conn
+            .translate_coordinates(wnd, root, 0, 0)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+        {
+            Some(origin) => origin,
+            None => {
+                failed += 1;
+                continue;
+            }
+        };
+
+        // include the decorations drawn by the window manager
+        let (left, right, top, bottom) =
+            match get_property32(conn, wnd, net_frame_extents, AtomEnum::CARDINAL)[..] {
+                [left, right, top, bottom] => {
+                    (left as i32, right as i32, top as i32, bottom as i32)
+                }
+                _ => (0, 0, 0, 0),
+            };
+
+        let bounds = Rectangle {
+            x: origin.dst_x as i32 - left - x,
+            y: origin.dst_y as i32 - top - y,
+            w: geometry.width as i32 + left + right,
+            h: geometry.height as i32 + top + bottom,
+        };
+
+        let title = get_title(conn, wnd, net_wm_name, utf8_string);
+
+        // print information about it for debug purposes
+        if cfg!(debug_assertions) {
+            eprintln!("Window {:#x}:", wnd);
+            eprintln!("  Title: {}", title);
+            eprintln!("  Bounds: {:?}", bounds);
+        }
+
+        // add the window to the list
+        windows.push(Window {
+            title,
+            bounds,
+            active: Some(wnd) == active,
+        });
+    }
+
+    (windows, failed)
+}
+
+// plain GetImage round-trip, the pixels go through the socket
+fn get_image(
+    conn: &RustConnection,
+    screen: &Screen,
+    bounds: Rectangle<i32>,
+) -> Result<Vec<u8>, CaptureError> {
+    let failed = |e: &dyn std::fmt::Display| {
+        CaptureError::failed(BACKEND, format!("cannot get the screen contents: {}", e))
+    };
+
+    let image = conn
+        .get_image(
+            ImageFormat::Z_PIXMAP,
+            screen.root,
+            bounds.x as i16,
+            bounds.y as i16,
+            bounds.w as u16,
+            bounds.h as u16,
+            !0,
+        )
+        .map_err(|e| failed(&e))?
+        .reply()
+        .map_err(|e| failed(&e))?;
+
+    zpixmap_to_rgb(
+        conn,
+        screen,
+        image.depth,
+        image.visual,
+        &image.data,
+        bounds.w as usize,
+        bounds.h as usize,
+    )
+}
+
+// MIT-SHM capture: the server writes the pixels straight into a segment it
+// shares with us. returns None whenever the extension can't be used.
+fn get_image_shm(
+    conn: &RustConnection,
+    screen: &Screen,
+    bounds: Rectangle<i32>,
+) -> Option<Result<Vec<u8>, CaptureError>> {
+    // server-allocated segments (passed as a fd) need MIT-SHM 1.2
+    let version = conn.shm_query_version().ok()?.reply().ok()?;
+
+    if (version.major_version, version.minor_version) < (1, 2) {
+        return None;
+    }
+
+    let (w, h) = (bounds.w as usize, bounds.h as usize);
+    let (_, stride) = zpixmap_layout(conn, screen.root_depth, w)?;
+    let size = stride * h;
+
+    let seg = conn.generate_id().ok()?;
+    let reply = conn
+        .shm_create_segment(seg, size as u32, false)
+        .ok()?
+        .reply()
+        .ok()?;
+
+    let segment = ShmSegmentWrapper::map(conn, seg, reply.shm_fd.as_raw_fd(), size)?;
+
+    let image = conn
+        .shm_get_image(
+            screen.root,
+            bounds.x as i16,
+            bounds.y as i16,
+            bounds.w as u16,
+            bounds.h as u16,
+            !0,
+            ImageFormat::Z_PIXMAP.into(),
+            seg,
+            0,
+        )
+        .ok()?
+        .reply()
+        .ok()?;
+
+    Some(zpixmap_to_rgb(
+        conn,
+        screen,
+        image.depth,
+        image.visual,
+        segment.data(),
+        w,
+        h,
+    ))
+}
+
+// a mapped MIT-SHM segment, detached and unmapped on drop
+struct ShmSegmentWrapper<'a> {
+    conn: &'a RustConnection,
+    seg: u32,
+    ptr: *mut libc::c_void,
+    size: usize,
+}
+
+impl<'a> ShmSegmentWrapper<'a> {
+    fn map(conn: &'a RustConnection, seg: u32, fd: i32, size: usize) -> Option<Self> {
+        let ptr = unsafe { libc::mmap(null_mut(), size, libc::PROT_READ, libc::MAP_SHARED, fd, 0) };
+
+        if ptr == libc::MAP_FAILED {
+            let _ = conn.shm_detach(seg);
+            return None;
+        }
+
+        Some(ShmSegmentWrapper {
+            conn,
+            seg,
+            ptr,
+            size,
+        })
+    }
+
+    fn data(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.size) }
+    }
+}
+
+impl Drop for ShmSegmentWrapper<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.size);
+        }
+
+        let _ = self.conn.shm_detach(self.seg);
+        let _ = self.conn.flush();
+    }
+}
+
+fn find_visual(screen: &Screen, visual: u32) -> Option<&Visualtype> {
+    screen
+        .allowed_depths
+        .iter()
+        .flat_map(|d| d.visuals.iter())
+        .find(|v| v.visual_id == visual)
+}
+
+// scales a masked channel to 8 bits
+fn channel(pixel: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let value = (pixel & mask) >> mask.trailing_zeros();
+    let max = mask >> mask.trailing_zeros();
+
+    (value * 255 / max) as u8
+}
+
+// bytes per pixel and per row of a ZPixmap image
+fn zpixmap_layout(conn: &RustConnection, depth: u8, w: usize) -> Option<(usize, usize)> {
+    let format = conn
+        .setup()
+        .pixmap_formats
+        .iter()
+        .find(|f| f.depth == depth)?;
+
+    let bytes_per_pixel = format.bits_per_pixel as usize / 8;
+    let pad = format.scanline_pad as usize / 8;
+
+    Some((bytes_per_pixel, (w * bytes_per_pixel).div_ceil(pad) * pad))
+}
+
+// how the pixels of a ZPixmap image are laid out
+struct PixelLayout {
+    bytes_per_pixel: usize,
+    stride: usize,
+    masks: [u32; 3],
+    lsb_first: bool,
+}
+
+impl PixelLayout {
+    // converts the image to bottom-up RGB rows, as the cropper expects
+    fn to_rgb(&self, image: &[u8], w: usize, h: usize) -> Vec<u8> {
+        let mut data = Vec::with_capacity(w * h * 3);
+
+        for row in image.chunks_exact(self.stride).take(h).rev() {
+            for px in row[..w * self.bytes_per_pixel].chunks_exact(self.bytes_per_pixel) {
+                let pixel = if self.lsb_first {
+                    px.iter().rev().fold(0u32, |acc, &b| acc << 8 | b as u32)
+                } else {
+                    px.iter().fold(0u32, |acc, &b| acc << 8 | b as u32)
+                };
+
+                for &mask in &self.masks {
+                    data.push(channel(pixel, mask));
+                }
+            }
+        }
+
+        data
+    }
+}
+
+// converts a ZPixmap image to bottom-up RGB rows, as the cropper expects
+fn zpixmap_to_rgb(
+    conn: &RustConnection,
+    screen: &Screen,
+    depth: u8,
+    visual: u32,
+    image: &[u8],
+    w: usize,
+    h: usize,
+) -> Result<Vec<u8>, CaptureError> {
+    let (bytes_per_pixel, stride) = zpixmap_layout(conn, depth, w).ok_or_else(|| {
+        CaptureError::failed(BACKEND, format!("no pixmap format for depth {}", depth))
+    })?;
+    let visual = find_visual(screen, visual).ok_or_else(|| {
+        CaptureError::failed(BACKEND, format!("no visual {:#x} on the screen", visual))
+    })?;
+
+    let layout = PixelLayout {
+        bytes_per_pixel,
+        stride,
+        masks: [visual.red_mask, visual.green_mask, visual.blue_mask],
+        lsb_first: conn.setup().image_byte_order == ImageOrder::LSB_FIRST,
+    };
+
+    Ok(layout.to_rgb(image, w, h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Instant;
+
+    #[test]
+    fn channel_scales_to_8_bits() {
+        assert_eq!(channel(0x00ab_cdef, 0x00ff_0000), 0xab);
+        assert_eq!(channel(0x00ab_cdef, 0x0000_00ff), 0xef);
+
+        // 5 bits of RGB565
+        assert_eq!(channel(0xf800, 0xf800), 255);
+        assert_eq!(channel(0x0800, 0xf800), 8);
+        assert_eq!(channel(0xffff, 0), 0);
+    }
+
+    #[test]
+    fn converts_bottom_up_and_skips_padding() {
+        // 2x2 RGB565, little endian, rows padded to 8 bytes
+        let layout = PixelLayout {
+            bytes_per_pixel: 2,
+            stride: 8,
+            masks: [0xf800, 0x07e0, 0x001f],
+            lsb_first: true,
+        };
+
+        #[rustfmt::skip]
+        let image = [
+            0x00, 0xf8, 0xe0, 0x07, 0xaa, 0xaa, 0xaa, 0xaa, // red, green
+            0x1f, 0x00, 0xff, 0xff, 0xaa, 0xaa, 0xaa, 0xaa, // blue, white
+        ];
+
+        assert_eq!(
+            layout.to_rgb(&image, 2, 2),
+            [0, 0, 255, 255, 255, 255, 255, 0, 0, 0, 255, 0]
+        );
+    }
+
+    #[test]
+    fn reads_msb_first_pixels() {
+        let layout = PixelLayout {
+            bytes_per_pixel: 4,
+            stride: 4,
+            masks: [0x00ff_0000, 0x0000_ff00, 0x0000_00ff],
+            lsb_first: false,
+        };
+
+        assert_eq!(
+            layout.to_rgb(&[0x00, 0x12, 0x34, 0x56], 1, 1),
+            [0x12, 0x34, 0x56]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs an X server, run with `xvfb-run cargo test -- --ignored`"]
+    fn captures_the_whole_screen() {
+        let snap = match take() {
+            Ok(snap) => snap,
+            Err(CaptureError::PartialWindows { snap, .. }) => *snap,
+            Err(e) => panic!("{}", e),
+        };
+
+        let (conn, screen_num) = x11rb::connect(None).unwrap();
+        let screen = &conn.setup().roots[screen_num];
+
+        assert_eq!((snap.bounds.x, snap.bounds.y), (0, 0));
+        assert_eq!(snap.bounds.w, screen.width_in_pixels as i32);
+        assert_eq!(snap.bounds.h, screen.height_in_pixels as i32);
+        assert_eq!(
+            snap.data.len(),
+            (snap.bounds.w * snap.bounds.h * 3) as usize
+        );
+    }
+
+    // on a big screen, where it matters: `xvfb-run -s "-screen 0 3840x2160x24"
+    // cargo test --release -- --ignored shm_is_not_slower`
+    #[test]
+    #[ignore = "needs an X server, run with `xvfb-run cargo test --release -- --ignored`"]
+    fn shm_is_not_slower_than_get_image() {
+        const ROUNDS: u32 = 20;
+
+        let (conn, screen_num) = x11rb::connect(None).unwrap();
+        let screen = conn.setup().roots[screen_num].clone();
+        let bounds = Rectangle {
+            x: 0,
+            y: 0,
+            w: screen.width_in_pixels as i32,
+            h: screen.height_in_pixels as i32,
+        };
+
+        let time = |capture: &dyn Fn() -> Option<Result<Vec<u8>, CaptureError>>| {
+            let started = Instant::now();
+
+            for _ in 0..ROUNDS {
+                let data = capture().expect("the capture failed").unwrap();
+                assert_eq!(data.len(), (bounds.w * bounds.h * 3) as usize);
+            }
+
+            started.elapsed() / ROUNDS
+        };
+
+        let shm = time(&|| get_image_shm(&conn, &screen, bounds));
+        let plain = time(&|| Some(get_image(&conn, &screen, bounds)));
+
+        // the same pixels either way
+        assert_eq!(
+            get_image_shm(&conn, &screen, bounds).unwrap().unwrap(),
+            get_image(&conn, &screen, bounds).unwrap()
+        );
+
+        assert!(
+            shm <= plain,
+            "{}x{}: MIT-SHM took {:?}, GetImage {:?}",
+            bounds.w,
+            bounds.h,
+            shm,
+            plain
+        );
+    }
+}
@@ -0,0 +1,15 @@
+#version 140
+
+uniform vec4 color;
+uniform vec4 border_color;
+uniform float border;
+
+in vec2 uv;
+
+out vec4 f_color;
+
+void main() {
+    bool edge = any(lessThan(uv, vec2(border))) || any(greaterThan(uv, vec2(1.0 - border)));
+
+    f_color = edge ? border_color : color;
+}
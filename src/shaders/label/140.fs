@@ -0,0 +1,14 @@
+#version 140
+
+uniform sampler2D tex;
+
+in vec2 uv;
+
+out vec4 f_color;
+
+void main() {
+    // one texel per font pixel, rows from the top
+    ivec2 size = textureSize(tex, 0);
+
+    f_color = texelFetch(tex, ivec2(uv * vec2(size)), 0);
+}
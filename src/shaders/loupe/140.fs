@@ -0,0 +1,49 @@
+#version 140
+
+uniform sampler2D tex;
+uniform vec2 cursor;
+uniform float pixels;
+uniform float zoom;
+
+in vec2 uv;
+
+out vec4 f_color;
+
+void main() {
+    ivec2 size = textureSize(tex, 0);
+
+    // uv goes down from the top, like the screen
+    vec2 cell = uv * pixels;
+    float half_pixels = floor(pixels / 2.0);
+    vec2 pixel = floor(cursor) + floor(cell) - half_pixels;
+
+    // the texture goes up from the bottom, nearest pixel only
+    vec3 color = vec3(0.0);
+    if (all(greaterThanEqual(pixel, vec2(0.0))) && all(lessThan(pixel, vec2(size)))) {
+        color = texelFetch(tex, ivec2(pixel.x, float(size.y) - 1.0 - pixel.y), 0).rgb;
+    }
+
+    // in screen pixels, from the loupe's corner and the cursor pixel's
+    vec2 screen = uv * pixels * zoom;
+    vec2 local = (cell - half_pixels) * zoom;
+
+    bool grid = any(lessThan(fract(cell) * zoom, vec2(1.0)));
+    bool center = all(greaterThanEqual(local, vec2(0.0))) && all(lessThan(local, vec2(zoom)));
+    bool frame = !center && all(greaterThanEqual(local, vec2(-1.0))) && all(lessThan(local, vec2(zoom + 1.0)));
+    bool crosshair = floor(cell.x) == half_pixels || floor(cell.y) == half_pixels;
+
+    if (frame) {
+        color = vec3(1.0);
+    } else if (crosshair && !center) {
+        color = mix(color, vec3(0.3, 0.6, 1.0), 0.35);
+    } else if (grid) {
+        color = mix(color, vec3(0.0), 0.25);
+    }
+
+    // the border
+    if (any(lessThan(screen, vec2(2.0))) || any(greaterThan(screen, vec2(pixels * zoom - 2.0)))) {
+        color = vec3(1.0);
+    }
+
+    f_color = vec4(color, 1.0);
+}
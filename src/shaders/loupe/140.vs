@@ -0,0 +1,13 @@
+#version 140
+
+uniform vec4 bounds;
+
+in vec2 pos;
+
+out vec2 uv;
+
+void main() {
+    uv = pos;
+
+    gl_Position = vec4((bounds.xy + pos * bounds.zw) * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,286 @@
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local,
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+// where saved screenshots go by default
+pub const DEFAULT: &str = "~/Pictures/screensnap/%Y-%m-%d_%H-%M-%S.png";
+
+// what a filename template can refer to
+pub struct Context<'a> {
+    pub time: DateTime<Local>,
+    pub window: Option<&'a str>,
+    pub monitor: Option<&'a str>,
+    pub width: u32,
+    pub height: u32,
+}
+
+// a filename template: strftime codes (%Y, %H...) and placeholders ({window},
+// {monitor}, {width}, {height}, {counter}, {counter:N} to pad to N digits)
+#[derive(Debug, Clone)]
+pub struct Template(String);
+
+impl Template {
+    pub fn new<S: Into<String>>(template: S) -> Self {
+        Template(template.into())
+    }
+
+    // creates the first file that doesn't exist yet, and its directories.
+    // {counter} counts up from 1, and without it a "-N" suffix goes before
+    // the extension. create_new so that two saves in the same second (from
+    // the daemon and the command line) don't overwrite each other
+    pub fn create(&self, ctx: &Context) -> io::Result<(PathBuf, File)> {
+        let has_counter = self.0.contains("{counter");
+
+        for n in 1.. {
+            let path = match has_counter {
+                true => self.expand(ctx, n),
+                false => with_suffix(&self.expand(ctx, 0), n),
+            };
+
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((path, file)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        unreachable!()
+    }
+
+    fn expand(&self, ctx: &Context, counter: u32) -> PathBuf {
+        let mut expanded = String::new();
+        let mut rest = self.0.as_str();
+
+        // ~ is the home directory
+        if let Some(path) = rest.strip_prefix("~/") {
+            if let Some(home) = home_dir() {
+                expanded.push_str(&home.to_string_lossy());
+                expanded.push('/');
+                rest = path;
+            }
+        }
+
+        while !rest.is_empty() {
+            let start = rest.find('{').unwrap_or(rest.len());
+            expand_time(&mut expanded, &rest[..start], &ctx.time);
+            rest = &rest[start..];
+
+            if rest.is_empty() {
+                break;
+            }
+
+            // unknown or unclosed placeholders are kept as they are
+            let end = match rest.find('}') {
+                Some(end) => end,
+                None => {
+                    expanded.push_str(rest);
+                    break;
+                }
+            };
+
+            match placeholder(&rest[1..end], ctx, counter) {
+                Some(value) => expanded.push_str(&sanitize(&value)),
+                None => expanded.push_str(&rest[..=end]),
+            }
+
+            rest = &rest[end + 1..];
+        }
+
+        PathBuf::from(expanded)
+    }
+}
+
+fn placeholder(name: &str, ctx: &Context, counter: u32) -> Option<String> {
+    match name {
+        "window" => Some(ctx.window.unwrap_or("screen").to_owned()),
+        "monitor" => Some(ctx.monitor.unwrap_or("screen").to_owned()),
+        "width" => Some(ctx.width.to_string()),
+        "height" => Some(ctx.height.to_string()),
+        "counter" => Some(counter.to_string()),
+        _ => {
+            let digits = name.strip_prefix("counter:")?.parse::<usize>().ok()?;
+            Some(format!("{:0digits$}", counter, digits = digits))
+        }
+    }
+}
+
+// strftime codes are expanded one by one, so that literal slashes still
+// separate directories but expanded ones (like %D) don't
+fn expand_time(expanded: &mut String, text: &str, time: &DateTime<Local>) {
+    let mut rest = text;
+
+    while let Some(start) = rest.find('%') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        // the code ends at its letter, after flags (%-d) or widths (%3f)
+        let end = rest[1..]
+            .find(|c: char| c.is_ascii_alphabetic() || c == '%')
+            .map(|i| i + 2)
+            .unwrap_or(rest.len());
+        let code = &rest[..end];
+        let items: Vec<Item> = StrftimeItems::new(code).collect();
+
+        // chrono doesn't know that code, keep it as is
+        match items.contains(&Item::Error) {
+            true => expanded.push_str(code),
+            false => expanded.push_str(&sanitize(
+                &time.format_with_items(items.into_iter()).to_string(),
+            )),
+        }
+
+        rest = &rest[end..];
+    }
+
+    expanded.push_str(rest);
+}
+
+// replaces what filesystems don't like in a single path component
+fn sanitize(value: &str) -> String {
+    let sanitized: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    // windows doesn't like trailing dots and spaces, and ".." would escape
+    let trimmed = sanitized.trim_end_matches(['.', ' ']);
+
+    match trimmed {
+        "" => String::from("_"),
+        trimmed => trimmed.to_owned(),
+    }
+}
+
+// "shot.png" becomes "shot-2.png"
+fn with_suffix(path: &Path, n: u32) -> PathBuf {
+    if n <= 1 {
+        return path.to_owned();
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, n),
+    };
+
+    path.with_file_name(name)
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" }).map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn context() -> Context<'static> {
+        Context {
+            time: Local.with_ymd_and_hms(2024, 3, 9, 14, 5, 7).unwrap(),
+            window: Some("Firefox: a/b"),
+            monitor: None,
+            width: 800,
+            height: 600,
+        }
+    }
+
+    fn expand(template: &str, counter: u32) -> String {
+        Template::new(template)
+            .expand(&context(), counter)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn expands_codes_and_placeholders() {
+        assert_eq!(
+            expand("shots/%Y-%m-%d_%H-%M-%S.png", 0),
+            "shots/2024-03-09_14-05-07.png"
+        );
+        assert_eq!(
+            expand("{window} on {monitor} {width}x{height}.png", 0),
+            "Firefox_ a_b on screen 800x600.png"
+        );
+
+        // expanded codes don't make directories, literal slashes do
+        assert_eq!(expand("%D/shot.png", 0), "03_09_24/shot.png");
+
+        // unknown things are left alone
+        assert_eq!(
+            expand("{nope} {counter:x} %Q {open", 0),
+            "{nope} {counter:x} %Q {open"
+        );
+    }
+
+    #[test]
+    fn pads_counters() {
+        assert_eq!(expand("shot-{counter}.png", 7), "shot-7.png");
+        assert_eq!(expand("shot-{counter:3}.png", 7), "shot-007.png");
+        assert_eq!(expand("shot-{counter:2}.png", 123), "shot-123.png");
+    }
+
+    #[test]
+    fn sanitizes_components() {
+        assert_eq!(sanitize("a/b\\c:d*e?f\"g<h>i|j"), "a_b_c_d_e_f_g_h_i_j");
+        assert_eq!(sanitize("tab\there"), "tab_here");
+        assert_eq!(sanitize("trailing. . "), "trailing");
+        assert_eq!(sanitize(".."), "_");
+        assert_eq!(sanitize(""), "_");
+        assert_eq!(sanitize("résumé"), "résumé");
+    }
+
+    #[test]
+    fn suffixes_before_the_extension() {
+        assert_eq!(
+            with_suffix(Path::new("a/shot.png"), 1),
+            Path::new("a/shot.png")
+        );
+        assert_eq!(
+            with_suffix(Path::new("a/shot.png"), 2),
+            Path::new("a/shot-2.png")
+        );
+        assert_eq!(
+            with_suffix(Path::new("a/shot.tar.gz"), 3),
+            Path::new("a/shot.tar-3.gz")
+        );
+        assert_eq!(with_suffix(Path::new("a/shot"), 2), Path::new("a/shot-2"));
+    }
+
+    #[test]
+    fn never_reuses_a_file() {
+        let dir = std::env::temp_dir().join(format!("screensnap-template-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let name = |path: PathBuf| path.file_name().unwrap().to_string_lossy().into_owned();
+        let create = |template: &str| {
+            let template = Template::new(dir.join(template).to_string_lossy().into_owned());
+            name(template.create(&context()).unwrap().0)
+        };
+
+        assert_eq!(create("shot.png"), "shot.png");
+        assert_eq!(create("shot.png"), "shot-2.png");
+        assert_eq!(create("shot.png"), "shot-3.png");
+
+        assert_eq!(create("{counter:2}.png"), "01.png");
+        assert_eq!(create("{counter:2}.png"), "02.png");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}